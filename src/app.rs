@@ -1,11 +1,35 @@
-use std::{collections::VecDeque, sync::mpsc::Receiver, thread::JoinHandle};
+use std::{error::Error, sync::mpsc::Receiver, thread::JoinHandle};
 
-use egui::{ahash::HashMap, epaint::ColorMode, Color32, Context, Layout, ScrollArea, Stroke, Ui};
+use egui::{ahash::HashMap, Color32, Context, Layout, ScrollArea, Stroke, Ui};
 use egui_extras::Column;
 use rumqttc::{Client, Event};
 use serde::{Deserialize, Serialize};
 
-use crate::mqtt_servermanager::{MqttServerManager, MqttServerManagerEvent, Server};
+use crate::{
+    bridge::BridgesWindow,
+    diff::DiffView,
+    loadtest::LoadTestPanel,
+    message_buffer::{BufferLimits, MessageBuffer},
+    mqtt_servermanager::{MqttServerManager, MqttServerManagerEvent, Server},
+    packet_log::{self, PacketLogFilter},
+    preview::{self, DetailView},
+    probe,
+    profiles::ProfilesWindow,
+    publish::PublishPanel,
+    retained::RetainedPanel,
+    rules::RulesWindow,
+    scheduler::SchedulePanel,
+    script::ScriptsWindow,
+    secrets::{SecretRef, SecretStore},
+    shared_test::SharedTestPanel,
+    simulator::SimulatorsWindow,
+    subscription::{SubStatus, Subscription},
+    sys_dashboard::{self, SYS_FILTER},
+    topic,
+    traffic::TrafficStats,
+};
+
+const MESSAGE_ROW_HEIGHT: f32 = 18.0;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -28,33 +52,95 @@ pub struct TemplateApp {
     mqtt_receiver: Option<Receiver<Event>>,
     #[serde(skip)]
     incoming: Vec<MqttServerManagerEvent>,
+    #[serde(skip)]
+    profiles: ProfilesWindow,
+    #[serde(skip)]
+    secrets: SecretStore,
+    #[serde(skip)]
+    unlock_display: bool,
+    #[serde(skip)]
+    unlock_prompted: bool,
+    #[serde(skip)]
+    master_password: String,
+    #[serde(skip)]
+    unlock_error: String,
     servers: MqttServers,
+    bridges: BridgesWindow,
+    scripts: ScriptsWindow,
+    rules: RulesWindow,
+    simulators: SimulatorsWindow,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct MqttServer {
     display: bool,
     edit_display: bool,
     name: String,
     host: String,
     port: String,
+    username: String,
+    #[serde(skip)]
+    password: String,
+    password_ref: SecretRef,
+    #[serde(skip)]
+    password_dirty: bool,
     #[serde(skip)]
     new_subscription: String,
     #[serde(skip)]
-    new_pub_topic: String,
+    new_shared: bool,
+    #[serde(skip)]
+    new_share_group: String,
+    publish: PublishPanel,
+    schedules: SchedulePanel,
+    load_test: LoadTestPanel,
+    retained: RetainedPanel,
+    shared_test: SharedTestPanel,
+    probe_enabled: bool,
+    sys_display: bool,
+    packets_display: bool,
     #[serde(skip)]
-    new_pub_payload: String,
-    subscriptions: Vec<String>,
+    packet_filter: PacketLogFilter,
+    traffic_display: bool,
     #[serde(skip)]
-    messages: VecDeque<MqttServerManagerEvent>,
+    traffic: TrafficStats,
+    subscriptions: Vec<Subscription>,
+    #[serde(skip)]
+    messages: MessageBuffer,
+    #[serde(default = "default_max_messages")]
     max_messages: usize,
-    table_messages: usize,
+    #[serde(default = "default_max_buffer_mb")]
+    max_buffer_mb: usize,
+    /// Messages kept per topic, zero keeps all.
+    keep_per_topic: usize,
+    /// Store compressed payloads as they arrive instead of decompressing them.
+    keep_compressed: bool,
+    /// Sequence numbers of the messages selected for comparison, oldest first.
+    #[serde(skip)]
+    selected: Vec<u64>,
+    #[serde(skip)]
+    diff: DiffView,
+    #[serde(skip)]
+    detail: DetailView,
+    /// Sequence number of the first message left out while the table is frozen.
+    #[serde(skip)]
+    frozen_at: Option<u64>,
+}
+
+fn default_max_messages() -> usize {
+    10_000
+}
+
+fn default_max_buffer_mb() -> usize {
+    256
 }
 
 impl MqttServer {
     pub fn new() -> Self {
         Self {
             edit_display: true,
+            max_messages: default_max_messages(),
+            max_buffer_mb: default_max_buffer_mb(),
             ..Self::default()
         }
     }
@@ -74,6 +160,35 @@ impl MqttServer {
             self.host.clone()
         }
     }
+    pub fn credentials(&self) -> Option<(String, String)> {
+        if self.username.is_empty() {
+            return None;
+        }
+        Some((self.username.clone(), self.password.clone()))
+    }
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+    pub fn set_password(&mut self, password: String) {
+        self.password = password;
+        self.password_dirty = true;
+    }
+    /// Copy of the persisted settings, stripped of window state and optionally secrets.
+    pub fn to_profile(&self, include_secrets: bool) -> Self {
+        let mut profile = Self {
+            display: false,
+            edit_display: false,
+            ..self.clone()
+        };
+        profile.messages.clear();
+        profile.traffic.clear();
+        profile.publish.clear_history();
+        profile.password_ref = SecretRef::None;
+        if !include_secrets {
+            profile.password.clear();
+        }
+        profile
+    }
 }
 
 #[derive(Default, Serialize, Deserialize)]
@@ -81,6 +196,180 @@ struct MqttServers {
     servers: HashMap<u32, MqttServer>,
 }
 
+/// Status of a subscription as acknowledged by the broker, with retry on failure.
+fn sub_status_ui(ui: &mut Ui, server: &Server, filter: &str) {
+    let retry = match server.sub_status(filter) {
+        None => false,
+        Some(SubStatus::Pending) => {
+            ui.small("pending");
+            false
+        }
+        Some(SubStatus::Granted(qos)) => {
+            ui.small(format!("granted QoS {}", qos as u8));
+            false
+        }
+        Some(SubStatus::Unsubscribed) => {
+            ui.small("unsubscribed");
+            false
+        }
+        Some(SubStatus::Rejected) => {
+            ui.colored_label(Color32::RED, "rejected by broker (0x80)");
+            ui.small_button("retry").clicked()
+        }
+        Some(SubStatus::Failed(e)) => {
+            ui.colored_label(Color32::RED, format!("failed: {}", e));
+            ui.small_button("retry").clicked()
+        }
+    };
+    if retry {
+        if let Err(e) = server.retry_sub(filter) {
+            log::error!("Cannot retry subscription '{}': '{}'", filter, e);
+        }
+    }
+}
+
+/// Newest first, only the visible rows are laid out. While frozen, messages that
+/// arrived later are left out so the rows stay in place.
+fn message_table(ui: &mut Ui, server: &mut MqttServer) {
+    let rows = match server.frozen_at {
+        Some(seq) => server.messages.count_before(seq),
+        None => server.messages.len(),
+    };
+    server
+        .selected
+        .retain(|seq| server.messages.find(*seq).is_some());
+    ui.horizontal(|ui| {
+        ui.small(format!("{} of 2 selected", server.selected.len()));
+        if let [old, new] = server.selected[..] {
+            if ui.small_button("diff selected").clicked() {
+                if let (Some(old), Some(new)) =
+                    (server.messages.find(old), server.messages.find(new))
+                {
+                    server.diff.compare(old, new);
+                }
+            }
+        }
+        if !server.selected.is_empty() && ui.small_button("clear").clicked() {
+            server.selected.clear();
+        }
+    });
+    let mut clicked = None;
+    let mut diff_previous = None;
+    let mut details = None;
+    egui_extras::TableBuilder::new(ui)
+        .sense(egui::Sense::click())
+        .column(Column::auto().at_least(20.0))
+        .column(Column::auto().at_least(20.0))
+        .column(Column::remainder())
+        .resizable(true)
+        .striped(true)
+        .header(20.0, |mut header| {
+            header.col(|ui| {
+                ui.strong("Topic");
+            });
+            header.col(|ui| {
+                ui.strong("Subscription");
+            });
+            header.col(|ui| {
+                ui.strong("Payload");
+            });
+        })
+        .body(|body| {
+            body.rows(MESSAGE_ROW_HEIGHT, rows, |mut row| {
+                let Some(message) = server.messages.get(rows - 1 - row.index()) else {
+                    return;
+                };
+                let event = &message.event;
+                let subs: Vec<&Subscription> = message
+                    .subscriptions
+                    .iter()
+                    .filter_map(|filter| server.subscriptions.iter().find(|s| &s.filter == filter))
+                    .collect();
+                let tint = subs.iter().find_map(|sub| sub.color);
+                row.set_selected(server.selected.contains(&message.seq));
+                row.col(|ui| {
+                    tint_cell(ui, tint);
+                    ui.add(egui::Label::new(event.topic.as_str()).truncate());
+                });
+                row.col(|ui| {
+                    tint_cell(ui, tint);
+                    let labels: Vec<&str> = subs.iter().map(|sub| sub.label()).collect();
+                    ui.add(egui::Label::new(labels.join(", ")).truncate());
+                });
+                row.col(|ui| {
+                    tint_cell(ui, tint);
+                    let mut payload = match preview::summary(&event.payload) {
+                        Some(summary) => summary,
+                        None => String::from_utf8_lossy(&event.payload).into_owned(),
+                    };
+                    if let Some((compression, size)) = message.compressed {
+                        payload = format!(
+                            "[{} {} → {}] {}",
+                            compression.name(),
+                            preview::format_size(size),
+                            preview::format_size(event.payload.len()),
+                            payload
+                        );
+                    }
+                    ui.add(egui::Label::new(payload).truncate());
+                });
+                let response = row.response();
+                if response.double_clicked() {
+                    details = Some(message.seq);
+                } else if response.clicked() {
+                    clicked = Some(message.seq);
+                }
+                response.context_menu(|ui| {
+                    if ui.button("details").clicked() {
+                        details = Some(message.seq);
+                        ui.close_menu();
+                    }
+                    if ui.button("diff with previous on topic").clicked() {
+                        diff_previous = Some(message.seq);
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+    if let Some(seq) = clicked {
+        match server.selected.iter().position(|s| *s == seq) {
+            Some(i) => {
+                server.selected.remove(i);
+            }
+            None => {
+                server.selected.push(seq);
+                if server.selected.len() > 2 {
+                    server.selected.remove(0);
+                }
+                server.selected.sort_unstable();
+            }
+        }
+    }
+    if let Some(message) = details.and_then(|seq| server.messages.find(seq)) {
+        server.detail.show(message);
+    }
+    if let Some(seq) = diff_previous {
+        match (
+            server.messages.previous_on_topic(seq),
+            server.messages.find(seq),
+        ) {
+            (Some(old), Some(new)) => server.diff.compare(old, new),
+            _ => log::info!(
+                "No earlier message on the topic of message {} buffered",
+                seq
+            ),
+        }
+    }
+}
+
+/// Paints the subscription colour behind a table cell.
+fn tint_cell(ui: &Ui, tint: Option<Color32>) {
+    if let Some(color) = tint {
+        ui.painter()
+            .rect_filled(ui.max_rect(), 0.0, color.gamma_multiply(0.3));
+    }
+}
+
 impl MqttServers {
     fn add_server(&mut self, server: MqttServer) {
         let id = fastrand::u32(0..u32::MAX);
@@ -88,12 +377,64 @@ impl MqttServers {
     }
     fn push(&mut self, event: MqttServerManagerEvent) {
         if let Some(server) = self.servers.get_mut(&event.client) {
-            server.messages.push_back(event);
-            if server.messages.len() > server.max_messages {
-                server.messages.pop_front();
+            // messages of internal subscriptions are only wanted here if the user
+            // subscribed to them as well
+            let topic = &event.event.topic;
+            let mut subscriptions = vec![];
+            for sub in server.subscriptions.iter_mut() {
+                if sub.enabled && topic::matches(&sub.filter, topic) {
+                    sub.count += 1;
+                    subscriptions.push(sub.filter.clone());
+                }
             }
+            if subscriptions.is_empty() {
+                return;
+            }
+            server.traffic.record(topic, event.event.payload.len());
+            let limits = BufferLimits {
+                max_messages: server.max_messages,
+                max_bytes: server.max_buffer_mb * 1024 * 1024,
+                keep_per_topic: server.keep_per_topic,
+            };
+            server
+                .messages
+                .push(event.event, subscriptions, !server.keep_compressed, &limits);
         }
     }
+    /// Moves changed passwords into the secret store, keeping only a reference.
+    fn store_secrets(&mut self, secrets: &mut SecretStore) -> Result<(), Box<dyn Error>> {
+        for (id, server) in self.servers.iter_mut().filter(|(_, s)| s.password_dirty) {
+            if server.password.is_empty() {
+                secrets.delete(&server.password_ref)?;
+                server.password_ref = SecretRef::None;
+            } else {
+                server.password_ref =
+                    secrets.store(&format!("{:x}/password", id), &server.password)?;
+            }
+            server.password_dirty = false;
+        }
+        Ok(())
+    }
+    /// Deletes the stored passwords of all servers.
+    fn delete_secrets(&self, secrets: &mut SecretStore) {
+        for (id, server) in &self.servers {
+            if let Err(e) = secrets.delete(&server.password_ref) {
+                log::error!("Cannot delete password of '{:x}': '{}'", id, e);
+            }
+        }
+    }
+    /// Resolves password references, returns false if some need an unlocked vault.
+    fn load_secrets(&mut self, secrets: &SecretStore) -> bool {
+        let mut resolved = true;
+        for (id, server) in self.servers.iter_mut().filter(|(_, s)| !s.password_dirty) {
+            match secrets.load(&server.password_ref) {
+                Ok(Some(password)) => server.password = password,
+                Ok(None) => resolved = false,
+                Err(e) => log::error!("Cannot load password of '{:x}': '{}'", id, e),
+            }
+        }
+        resolved
+    }
     fn _get(&self, id: &u32) -> Option<&MqttServer> {
         self.servers.get(id)
     }
@@ -121,9 +462,13 @@ impl MqttServers {
                                 let ctx = ui.ctx().clone();
                                 manager.connect(*id, server, ctx);
                             }
-                        } else {
-                            if ui.add(egui::Button::new("d")).clicked() {
-                                manager.disconnect(*id);
+                        } else if ui.add(egui::Button::new("d")).clicked() {
+                            manager.disconnect(*id);
+                        }
+                        if let Some(mqtt_server) = manager.servers().get(id) {
+                            if let Some(stats) = mqtt_server.probe().stats() {
+                                probe::sparkline(ui, &mqtt_server.probe().history());
+                                ui.small(format!("{:.1} ms", stats.current));
                             }
                         }
                     });
@@ -135,83 +480,104 @@ impl MqttServers {
     fn windows(&mut self, ctx: &Context, manager: &mut MqttServerManager) {
         for (id, server) in self.servers.iter_mut() {
             let connected = manager.servers().contains_key(id);
+            let mut display = server.display;
             egui::Window::new(format!("MQTT: {}", server.name()))
                 .id(format!("mqtt_{}", id).into())
-                .open(&mut server.display)
+                .open(&mut display)
                 .default_width(150.0)
                 .default_height(150.0)
                 .show(ctx, |ui| {
                     ui.small(format!("'{:x}' connected: {}", id, connected));
-                    ui.small(format!("'{:x}' messages: {}", id, server.messages.len()));
-                    ui.separator();
+                    ui.small(format!(
+                        "'{:x}' messages: {} in {} topics, {:.1} of {} MB",
+                        id,
+                        server.messages.len(),
+                        server.messages.topic_count(),
+                        server.messages.bytes() as f64 / (1024.0 * 1024.0),
+                        server.max_buffer_mb
+                    ));
                     ui.horizontal(|ui| {
-                        ui.add(egui::Slider::new(
-                            &mut server.table_messages,
-                            (0 as usize)..=(10_000 as usize),
-                        ));
-                        ui.label("max rendered messages in table");
+                        if ui.button("$SYS").clicked() {
+                            server.sys_display = true;
+                        }
+                        if ui.button("packets").clicked() {
+                            server.packets_display = true;
+                        }
+                        if ui.button("traffic").clicked() {
+                            server.traffic_display = true;
+                        }
+                        ui.checkbox(&mut server.probe_enabled, "latency probe");
+                        let probe = manager.servers().get(id).map(|s| s.probe());
+                        if let Some(stats) = probe.and_then(|probe| probe.stats()) {
+                            ui.small(format!(
+                                "current {:.1} ms, p50 {:.1} ms, p99 {:.1} ms",
+                                stats.current, stats.p50, stats.p99
+                            ));
+                        }
                     });
+                    if let Some(mqtt_server) = manager.servers().get(id) {
+                        if server.probe_enabled {
+                            probe::plot(ui, *id, &mqtt_server.probe().history());
+                        }
+                    }
+                    ui.separator();
+                    server.publish.ui(ui, manager.servers().get(id));
+                    server.schedules.ui(ui, *id, manager, server.publish.form());
+                    if let Some(config) = server.load_test.ui(ui, *id, manager) {
+                        manager.start_load_test(*id, server, config);
+                    }
+                    if let Some(filter) = server.retained.ui(ui, *id, manager) {
+                        manager.start_retained_scan(*id, server, filter);
+                    }
+                    if let Some(config) = server.shared_test.ui(ui, *id, manager) {
+                        manager.start_shared_test(*id, server, config);
+                    }
                     ui.separator();
                     ui.horizontal(|ui| {
-                        ui.add(
-                            egui::TextEdit::singleline(&mut server.new_pub_topic)
-                                .hint_text("topic")
-                                .interactive(connected),
-                        );
-                        ui.add(
-                            egui::TextEdit::singleline(&mut server.new_pub_payload)
-                                .hint_text("payload")
-                                .interactive(connected),
-                        );
-                        if ui.button("publish").clicked() {
-                            if let Some(connected_client) = manager.servers().get(id) {
-                                connected_client.publish(
-                                    server.new_pub_topic.clone(),
-                                    server.new_pub_payload.clone(),
-                                );
-                            }
-                        };
+                        let mut frozen = server.frozen_at.is_some();
+                        ui.selectable_value(&mut frozen, false, "follow");
+                        ui.selectable_value(&mut frozen, true, "frozen");
+                        if frozen != server.frozen_at.is_some() {
+                            server.frozen_at = frozen.then_some(server.messages.received());
+                        }
+                        if let Some(frozen_at) = server.frozen_at {
+                            let received = server.messages.received();
+                            ui.small(format!("{} new", received - frozen_at));
+                        }
                     });
-                    ui.separator();
-                    egui_extras::TableBuilder::new(ui)
-                        .column(Column::auto().at_least(20.0))
-                        .column(Column::remainder())
-                        .resizable(true)
-                        .striped(true)
-                        .header(20.0, |mut header| {
-                            header.col(|ui| {
-                                ui.strong("Topic");
-                            });
-                            header.col(|ui| {
-                                ui.strong("Payload");
-                            });
-                        })
-                        .body(|mut body| {
-                            let mut exit_count = 0;
-                            for message in server.messages.iter().rev() {
-                                if exit_count >= server.table_messages {
-                                    return;
-                                }
-                                let event = &message.event;
-                                body.row(10.0, |mut row| {
-                                    row.col(|ui| {
-                                        ui.label(event.topic.clone());
-                                    });
-                                    row.col(|ui| {
-                                        ui.label(
-                                            String::from_utf8(event.payload.to_vec())
-                                                .unwrap_or(String::from("ERROR PARSING UTF8")),
-                                        );
-                                    });
-                                    exit_count += 1;
-                                });
-                            }
-                        });
+                    message_table(ui, server);
                 });
+            server.display = display;
+            let name = server.name();
+            sys_dashboard::window(
+                ctx,
+                *id,
+                &name,
+                &mut server.sys_display,
+                manager.servers().get(id).map(|s| s.sys()),
+            );
+            packet_log::window(
+                ctx,
+                *id,
+                &name,
+                &mut server.packets_display,
+                &mut server.packet_filter,
+                manager.servers().get(id).map(|s| s.packets()),
+            );
+            server
+                .traffic
+                .window(ctx, *id, &name, &mut server.traffic_display);
+            server.diff.window(ctx, *id, &name);
+            server.detail.window(ctx, *id, &name);
         }
     }
 
-    fn edit_windows(&mut self, ctx: &Context, manager: &mut MqttServerManager) {
+    fn edit_windows(
+        &mut self,
+        ctx: &Context,
+        manager: &mut MqttServerManager,
+        secrets: &mut SecretStore,
+    ) {
         let mut delete_ids = vec![];
         for (id, server) in self.servers.iter_mut() {
             let connected = manager.servers().contains_key(id);
@@ -247,34 +613,112 @@ impl MqttServers {
                         ui.label("Alias");
                     });
                     ui.horizontal(|ui| {
-                        ui.add(egui::Slider::new(
-                            &mut server.max_messages,
-                            (0 as usize)..=(1_000_000 as usize),
-                        ));
+                        ui.add(
+                            egui::TextEdit::singleline(&mut server.username)
+                                .hint_text("username")
+                                .interactive(!connected),
+                        );
+                        ui.label("Username");
+                    });
+                    ui.horizontal(|ui| {
+                        let response = ui.add(
+                            egui::TextEdit::singleline(&mut server.password)
+                                .password(true)
+                                .interactive(!connected),
+                        );
+                        if response.changed() {
+                            server.password_dirty = true;
+                        }
+                        ui.label("Password");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut server.max_messages, 0..=1_000_000));
                         ui.label("max stored messages");
                     });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut server.max_buffer_mb, 1..=4096)
+                                .logarithmic(true)
+                                .suffix(" MB"),
+                        );
+                        ui.label("max stored bytes");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Slider::new(&mut server.keep_per_topic, 0..=10_000));
+                        ui.label("keep last messages per topic (0 keeps all)");
+                    });
+                    ui.checkbox(
+                        &mut server.keep_compressed,
+                        "keep gzip, zlib, deflate and zstd payloads compressed",
+                    );
                     ui.separator();
                     ui.heading("Subscriptions");
                     let mut delete_subs = vec![];
+                    let mqtt_server = manager.servers().get(id);
                     for sub in server.subscriptions.iter_mut() {
                         ui.horizontal(|ui| {
-                            ui.add(egui::TextEdit::singleline(sub).interactive(false));
+                            ui.checkbox(&mut sub.enabled, "")
+                                .on_hover_text("pause without deleting");
+                            ui.add(egui::TextEdit::singleline(&mut sub.filter).interactive(false));
+                            ui.add(
+                                egui::TextEdit::singleline(&mut sub.alias)
+                                    .hint_text("alias")
+                                    .desired_width(80.0),
+                            );
+                            let mut tinted = sub.color.is_some();
+                            if ui.checkbox(&mut tinted, "tint").changed() {
+                                sub.color = tinted.then_some(Color32::LIGHT_BLUE);
+                            }
+                            if let Some(color) = &mut sub.color {
+                                ui.color_edit_button_srgba(color);
+                            }
+                            ui.small(format!("{} messages", sub.count));
+                            if let Some((group, _)) = topic::shared(&sub.filter) {
+                                ui.small(format!("shared, group '{}'", group));
+                            }
+                            if let Err(e) = topic::validate_filter(&sub.filter) {
+                                ui.colored_label(Color32::RED, format!("not subscribed: {}", e));
+                            } else if let Some(mqtt_server) = mqtt_server {
+                                sub_status_ui(ui, mqtt_server, &sub.filter);
+                            }
                             if ui.button("Del").clicked() {
-                                delete_subs.push(sub.clone());
+                                delete_subs.push(sub.filter.clone());
                             }
                         });
                     }
                     server
                         .subscriptions
-                        .retain(|sub| !delete_subs.contains(sub));
+                        .retain(|sub| !delete_subs.contains(&sub.filter));
                     ui.horizontal(|ui| {
                         ui.add(
                             egui::TextEdit::singleline(&mut server.new_subscription).hint_text("#"),
                         );
-                        if ui.button("Add").clicked() {
-                            server.subscriptions.push(server.new_subscription.clone());
+                        ui.checkbox(&mut server.new_shared, "shared");
+                        if server.new_shared {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut server.new_share_group)
+                                    .hint_text("group")
+                                    .desired_width(80.0),
+                            );
+                        }
+                        let filter = match server.new_shared {
+                            true => topic::share(&server.new_share_group, &server.new_subscription),
+                            false => server.new_subscription.clone(),
+                        };
+                        let valid = topic::validate_filter(&filter);
+                        if ui
+                            .add_enabled(valid.is_ok(), egui::Button::new("Add"))
+                            .clicked()
+                            && !server.subscriptions.iter().any(|sub| sub.filter == filter)
+                        {
+                            server.subscriptions.push(Subscription::new(filter));
                             server.new_subscription.clear();
                         }
+                        if let Err(e) = valid {
+                            if !server.new_subscription.is_empty() {
+                                ui.colored_label(Color32::RED, e);
+                            }
+                        }
                     });
                     ui.separator();
                     // if !manager.servers().contains_key(id) {
@@ -300,13 +744,27 @@ impl MqttServers {
                         }
                     });
                 });
+            let mut internal_subs = manager.internal_filters(*id);
+            if server.sys_display {
+                internal_subs.push(String::from(SYS_FILTER));
+            }
             if let Some(mqtt_server) = manager.servers_mut().get_mut(id) {
-                mqtt_server.sync_subs(&server.subscriptions.clone());
+                if let Err(e) = mqtt_server.sync_subs(&server.subscriptions) {
+                    log::error!("Cannot sync subscriptions of '{}': '{}'", id, e);
+                }
+                mqtt_server.set_probe(server.probe_enabled);
+                if let Err(e) = mqtt_server.sync_internal_subs(&internal_subs) {
+                    log::error!("Cannot sync internal subscriptions of '{}': '{}'", id, e);
+                }
             }
         }
         for id in delete_ids {
             manager.disconnect(id);
-            self.servers.remove(&id);
+            if let Some(server) = self.servers.remove(&id) {
+                if let Err(e) = secrets.delete(&server.password_ref) {
+                    log::error!("Cannot delete password of '{:x}': '{}'", id, e);
+                }
+            }
         }
     }
 }
@@ -324,7 +782,17 @@ impl Default for TemplateApp {
             mqtt_jh: None,
             mqtt_receiver: None,
             incoming: vec![],
+            profiles: ProfilesWindow::default(),
+            secrets: SecretStore::new(),
+            unlock_display: false,
+            unlock_prompted: false,
+            master_password: String::new(),
+            unlock_error: String::new(),
             servers: MqttServers::default(),
+            bridges: BridgesWindow::default(),
+            scripts: ScriptsWindow::default(),
+            rules: RulesWindow::default(),
+            simulators: SimulatorsWindow::default(),
         }
     }
 }
@@ -337,17 +805,67 @@ impl TemplateApp {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        let mut app: Self = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        app.unlock_display = !app.servers.load_secrets(&app.secrets);
+        app.unlock_prompted = app.unlock_display;
+        app
+    }
+
+    fn store_secrets(&mut self) {
+        if let Err(e) = self.servers.store_secrets(&mut self.secrets) {
+            if self.secrets.is_locked() {
+                self.unlock_display |= !self.unlock_prompted;
+                self.unlock_prompted = true;
+            } else {
+                log::error!("Cannot store secrets: '{}'", e);
+            }
         }
+    }
 
-        Default::default()
+    fn unlock_window(&mut self, ctx: &Context) {
+        let mut unlocked = false;
+        egui::Window::new("Unlock Secrets")
+            .open(&mut self.unlock_display)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if self.secrets.vault_exists() {
+                    ui.label("Enter the master password of the encrypted password file.");
+                } else {
+                    ui.label(
+                        "OS keyring unavailable, choose a master password to encrypt passwords.",
+                    );
+                }
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.master_password).password(true));
+                    if ui.button("Unlock").clicked() {
+                        match self.secrets.unlock(&self.master_password) {
+                            Ok(()) => unlocked = true,
+                            Err(e) => self.unlock_error = e.to_string(),
+                        }
+                        self.master_password.clear();
+                    }
+                });
+                if !self.unlock_error.is_empty() {
+                    ui.colored_label(Color32::RED, &self.unlock_error);
+                }
+            });
+        if unlocked {
+            self.unlock_display = false;
+            self.unlock_prompted = false;
+            self.unlock_error.clear();
+            self.servers.load_secrets(&self.secrets);
+            self.store_secrets();
+        }
     }
 }
 
 impl eframe::App for TemplateApp {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.store_secrets();
         eframe::set_value(storage, eframe::APP_KEY, self);
     }
 
@@ -367,13 +885,44 @@ impl eframe::App for TemplateApp {
                 let is_web = cfg!(target_arch = "wasm32");
                 if !is_web {
                     ui.menu_button("File", |ui| {
+                        if ui.button("Import / Export Profiles").clicked() {
+                            self.profiles.open = true;
+                            ui.close_menu();
+                        }
                         if ui.button("Reset App Settings").clicked() {
+                            self.servers.delete_secrets(&mut self.secrets);
                             *self = Self::default();
                         }
                         if ui.button("Quit").clicked() {
                             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                         }
                     });
+                    ui.menu_button("Tools", |ui| {
+                        if ui.button("Bridges").clicked() {
+                            self.bridges.open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Scripts").clicked() {
+                            self.scripts.open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Rules & Alerts").clicked() {
+                            self.rules.open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Simulators").clicked() {
+                            self.simulators.open = true;
+                            ui.close_menu();
+                        }
+                    });
+                    let alerts = self.manager.rules().unacknowledged();
+                    if alerts > 0
+                        && ui
+                            .add(egui::Button::new(format!("{} alerts", alerts)).fill(Color32::RED))
+                            .clicked()
+                    {
+                        self.rules.open = true;
+                    }
                     ui.add_space(0.0);
                 }
                 egui::widgets::global_theme_preference_buttons(ui);
@@ -396,8 +945,26 @@ impl eframe::App for TemplateApp {
                 });
             });
 
+        for server in self.profiles.show(ctx, &self.servers.servers) {
+            self.servers.add_server(server);
+        }
+        self.bridges
+            .show(ctx, &mut self.manager, &self.servers.servers);
+        self.scripts
+            .show(ctx, &mut self.manager, &self.servers.servers);
+        self.rules
+            .show(ctx, &mut self.manager, &self.servers.servers);
+        self.simulators
+            .show(ctx, &mut self.manager, &self.servers.servers);
+        // don't hit the keyring on every keystroke
+        if ctx.memory(|memory| memory.focused().is_none()) {
+            self.store_secrets();
+        }
+        self.unlock_window(ctx);
+
         egui::CentralPanel::default().show(ctx, |_| {
-            self.servers.edit_windows(ctx, &mut self.manager);
+            self.servers
+                .edit_windows(ctx, &mut self.manager, &mut self.secrets);
             self.servers.windows(ctx, &mut self.manager);
         });
 
@@ -0,0 +1,279 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use egui::{ahash::HashMap, Color32, Context};
+use rumqttc::Publish;
+use serde::{Deserialize, Serialize};
+
+use crate::{app::MqttServer, mqtt_servermanager::MqttServerManager, publish::qos_combo, topic};
+
+/// How long a forwarded message is remembered to recognise it coming back.
+const LOOP_MEMORY: Duration = Duration::from_secs(10);
+
+/// Forwards messages matching `filter` from the `source` server to the `target` server.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BridgeConfig {
+    pub id: u32,
+    pub enabled: bool,
+    pub source: u32,
+    pub target: u32,
+    pub filter: String,
+    /// Removed from the start of forwarded topics.
+    pub strip_prefix: String,
+    /// Put in front of forwarded topics, after stripping.
+    pub add_prefix: String,
+    /// Forward with this QoS instead of the one of the received message.
+    pub qos: Option<u8>,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        BridgeConfig {
+            id: fastrand::u32(..),
+            enabled: false,
+            source: 0,
+            target: 0,
+            filter: String::from("#"),
+            strip_prefix: String::new(),
+            add_prefix: String::new(),
+            qos: None,
+        }
+    }
+}
+
+impl BridgeConfig {
+    pub fn rewrite(&self, topic: &str) -> String {
+        let topic = topic.strip_prefix(&self.strip_prefix).unwrap_or(topic);
+        format!("{}{}", self.add_prefix, topic)
+    }
+
+    /// Checks that the prefixes can be part of topic names.
+    pub fn validate_prefixes(&self) -> Result<(), String> {
+        if !self.strip_prefix.is_empty() {
+            topic::validate_topic(&self.strip_prefix)
+                .map_err(|e| format!("strip prefix: {}", e))?;
+        }
+        // prefixes of any length end up in a topic of at least one character
+        let rewritten = self.rewrite(&format!("{}x", self.strip_prefix));
+        topic::validate_topic(&rewritten).map_err(|e| format!("add prefix: {}", e))
+    }
+}
+
+/// A configured bridge with its counters.
+pub struct Bridge {
+    pub config: BridgeConfig,
+    pub forwarded: u64,
+    pub suppressed: u64,
+}
+
+/// Remembers forwarded messages, so a message arriving on a server it was forwarded to
+/// isn't sent back and forth between bridged servers.
+#[derive(Default)]
+pub struct LoopGuard {
+    /// Copies forwarded by target, topic and payload hash, forgotten once expired.
+    sent: HashMap<(u32, String, u64), Forwarded>,
+    pruned: Option<Instant>,
+}
+
+struct Forwarded {
+    count: u32,
+    expires: Instant,
+}
+
+impl LoopGuard {
+    pub fn remember(&mut self, target: u32, topic: &str, payload: &[u8]) {
+        let expires = Instant::now() + LOOP_MEMORY;
+        self.sent
+            .entry((target, topic.to_owned(), hash(payload)))
+            .and_modify(|forwarded| {
+                forwarded.count += 1;
+                forwarded.expires = expires;
+            })
+            .or_insert(Forwarded { count: 1, expires });
+    }
+
+    /// Whether `message` received on `server` is a copy forwarded by a bridge.
+    pub fn is_forwarded(&mut self, server: u32, message: &Publish) -> bool {
+        let now = Instant::now();
+        if !self
+            .pruned
+            .is_some_and(|pruned| now - pruned <= LOOP_MEMORY)
+        {
+            self.sent.retain(|_, forwarded| forwarded.expires > now);
+            self.pruned = Some(now);
+        }
+        let key = (server, message.topic.clone(), hash(&message.payload));
+        let Some(forwarded) = self.sent.get_mut(&key) else {
+            return false;
+        };
+        let live = forwarded.expires > now;
+        forwarded.count -= 1;
+        if forwarded.count == 0 || !live {
+            self.sent.remove(&key);
+        }
+        live
+    }
+}
+
+fn hash(payload: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgesWindow {
+    #[serde(skip)]
+    pub open: bool,
+    bridges: Vec<BridgeConfig>,
+}
+
+impl BridgesWindow {
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        manager: &mut MqttServerManager,
+        servers: &HashMap<u32, MqttServer>,
+    ) {
+        let server_name = |id: u32| {
+            servers
+                .get(&id)
+                .map(MqttServer::name)
+                .unwrap_or_else(|| String::from("-"))
+        };
+        egui::Window::new("Bridges")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                let mut delete = None;
+                for (index, bridge) in self.bridges.iter_mut().enumerate() {
+                    ui.push_id(bridge.id, |ui| {
+                        egui::Grid::new("bridge").show(ui, |ui| {
+                            ui.label("from");
+                            egui::ComboBox::from_id_salt("source")
+                                .selected_text(server_name(bridge.source))
+                                .show_ui(ui, |ui| {
+                                    for (id, server) in servers.iter() {
+                                        ui.selectable_value(&mut bridge.source, *id, server.name());
+                                    }
+                                });
+                            ui.label("to");
+                            egui::ComboBox::from_id_salt("target")
+                                .selected_text(server_name(bridge.target))
+                                .show_ui(ui, |ui| {
+                                    for (id, server) in servers.iter() {
+                                        ui.selectable_value(&mut bridge.target, *id, server.name());
+                                    }
+                                });
+                            ui.end_row();
+                            ui.label("filter");
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut bridge.filter);
+                                if let Err(e) = topic::validate_filter(&bridge.filter) {
+                                    ui.colored_label(Color32::RED, e);
+                                }
+                            });
+                            ui.label("QoS");
+                            ui.horizontal(|ui| {
+                                let mut fixed = bridge.qos.is_some();
+                                ui.checkbox(&mut fixed, "override");
+                                match (fixed, bridge.qos.as_mut()) {
+                                    (true, Some(qos)) => qos_combo(ui, "bridge_qos", qos),
+                                    (true, None) => bridge.qos = Some(0),
+                                    (false, _) => bridge.qos = None,
+                                }
+                            });
+                            ui.end_row();
+                            ui.label("strip prefix");
+                            ui.text_edit_singleline(&mut bridge.strip_prefix);
+                            ui.label("add prefix");
+                            ui.text_edit_singleline(&mut bridge.add_prefix);
+                            ui.end_row();
+                        });
+                        if let Err(e) = bridge.validate_prefixes() {
+                            ui.colored_label(Color32::RED, e);
+                        }
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut bridge.enabled, "enabled");
+                            if let Some(running) = manager.bridge(bridge.id) {
+                                ui.small(format!(
+                                    "{} forwarded, {} loops suppressed",
+                                    running.forwarded, running.suppressed
+                                ));
+                            }
+                            let connected = manager.servers().contains_key(&bridge.source)
+                                && manager.servers().contains_key(&bridge.target);
+                            if bridge.enabled && !connected {
+                                ui.small("waiting for both servers to connect");
+                            }
+                            if ui.button("Del").clicked() {
+                                delete = Some(index);
+                            }
+                        });
+                    });
+                    ui.separator();
+                }
+                if let Some(index) = delete {
+                    self.bridges.remove(index);
+                }
+                if ui.button("Add").clicked() {
+                    self.bridges.push(BridgeConfig::default());
+                }
+            });
+        manager.sync_bridges(&self.bridges);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str, payload: &str) -> Publish {
+        Publish::new(topic, rumqttc::QoS::AtMostOnce, payload)
+    }
+
+    #[test]
+    fn validates_prefixes() {
+        let bridge = |strip: &str, add: &str| BridgeConfig {
+            strip_prefix: strip.to_owned(),
+            add_prefix: add.to_owned(),
+            ..BridgeConfig::default()
+        };
+        assert!(bridge("", "").validate_prefixes().is_ok());
+        assert!(bridge("site/", "remote/").validate_prefixes().is_ok());
+        assert!(bridge("+/", "").validate_prefixes().is_err());
+        assert!(bridge("", "remote/#/").validate_prefixes().is_err());
+        assert!(bridge("", "a\0").validate_prefixes().is_err());
+        assert_eq!(bridge("site/", "remote/").rewrite("site/a"), "remote/a");
+    }
+
+    #[test]
+    fn loop_guard_counts_copies() {
+        let mut guard = LoopGuard::default();
+        guard.remember(1, "a", b"x");
+        guard.remember(1, "a", b"x");
+        assert!(!guard.is_forwarded(2, &message("a", "x")));
+        assert!(!guard.is_forwarded(1, &message("a", "y")));
+        assert!(!guard.is_forwarded(1, &message("b", "x")));
+        assert!(guard.is_forwarded(1, &message("a", "x")));
+        assert!(guard.is_forwarded(1, &message("a", "x")));
+        assert!(!guard.is_forwarded(1, &message("a", "x")));
+        assert!(guard.sent.is_empty());
+    }
+
+    #[test]
+    fn loop_guard_forgets_expired_copies() {
+        let mut guard = LoopGuard::default();
+        guard.remember(1, "a", b"x");
+        guard.remember(1, "b", b"x");
+        for forwarded in guard.sent.values_mut() {
+            forwarded.expires = Instant::now();
+        }
+        assert!(!guard.is_forwarded(1, &message("a", "x")));
+        assert!(guard.sent.is_empty());
+    }
+}
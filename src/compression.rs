@@ -0,0 +1,189 @@
+use std::io::{self, Read, Write};
+
+use egui::Ui;
+use flate2::{
+    read::{DeflateDecoder, GzDecoder, ZlibDecoder},
+    write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+};
+use serde::{Deserialize, Serialize};
+
+/// Limit for decompressed payloads, so a small message can't expand without bound.
+const MAX_DECOMPRESSED: u64 = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zlib,
+    Deflate,
+    Zstd,
+}
+
+impl Compression {
+    const ALL: [Compression; 5] = [
+        Compression::None,
+        Compression::Gzip,
+        Compression::Zlib,
+        Compression::Deflate,
+        Compression::Zstd,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zlib => "zlib",
+            Compression::Deflate => "deflate",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Recognizes gzip, zlib and zstd by their headers. Raw deflate has no header.
+    fn detect(payload: &[u8]) -> Self {
+        match payload {
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Compression::Zstd,
+            // deflate method, a window of at most 32 KB and a header checksum
+            // that is a multiple of 31
+            [cmf, flg, ..]
+                if cmf & 0x0f == 8
+                    && cmf >> 4 <= 7
+                    && u16::from_be_bytes([*cmf, *flg]) % 31 == 0 =>
+            {
+                Compression::Zlib
+            }
+            _ => Compression::None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Compression::Zlib => {
+                let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Compression::Zstd => zstd::encode_all(data, 0),
+        }
+    }
+
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        match self {
+            Compression::None => out.extend_from_slice(data),
+            Compression::Gzip => read_limited(GzDecoder::new(data), &mut out)?,
+            Compression::Zlib => read_limited(ZlibDecoder::new(data), &mut out)?,
+            Compression::Deflate => read_limited(DeflateDecoder::new(data), &mut out)?,
+            Compression::Zstd => read_limited(zstd::Decoder::new(data)?, &mut out)?,
+        }
+        Ok(out)
+    }
+}
+
+fn read_limited<R: Read>(reader: R, out: &mut Vec<u8>) -> io::Result<()> {
+    reader.take(MAX_DECOMPRESSED + 1).read_to_end(out)?;
+    if out.len() as u64 > MAX_DECOMPRESSED {
+        return Err(io::Error::other("decompressed payload too large"));
+    }
+    Ok(())
+}
+
+/// Decompresses `payload` if it looks compressed. Raw deflate is only assumed for
+/// binary payloads that inflate to text, as it can't be told apart otherwise.
+pub fn decompress(payload: &[u8]) -> Option<(Compression, Vec<u8>)> {
+    let detected = match Compression::detect(payload) {
+        Compression::None if std::str::from_utf8(payload).is_err() => Compression::Deflate,
+        Compression::None => return None,
+        detected => detected,
+    };
+    let decompressed = detected.decompress(payload).ok()?;
+    if detected == Compression::Deflate
+        && (decompressed.is_empty() || std::str::from_utf8(&decompressed).is_err())
+    {
+        return None;
+    }
+    Some((detected, decompressed))
+}
+
+pub fn compression_combo(ui: &mut Ui, id: &str, compression: &mut Compression) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(compression.name())
+        .width(70.0)
+        .show_ui(ui, |ui| {
+            for option in Compression::ALL {
+                ui.selectable_value(compression, option, option.name());
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "{\"temperature\": 21.5, \"humidity\": 40}";
+
+    #[test]
+    fn round_trips() {
+        for compression in [
+            Compression::Gzip,
+            Compression::Zlib,
+            Compression::Deflate,
+            Compression::Zstd,
+        ] {
+            let compressed = compression.compress(TEXT.as_bytes()).unwrap();
+            assert_ne!(compressed, TEXT.as_bytes());
+            let (detected, payload) = decompress(&compressed).unwrap();
+            assert!(detected == compression, "{}", compression.name());
+            assert_eq!(payload, TEXT.as_bytes());
+        }
+        assert!(Compression::None.compress(b"raw").unwrap() == b"raw");
+    }
+
+    #[test]
+    fn text_with_zlib_header_stays_text() {
+        // "hb" happens to be a valid zlib header
+        assert!(Compression::detect(b"hb") == Compression::Zlib);
+        assert!(decompress(b"hb 42").is_none());
+        assert!(decompress(TEXT.as_bytes()).is_none());
+        assert!(decompress(b"").is_none());
+    }
+
+    #[test]
+    fn binary_is_not_taken_for_deflate() {
+        let mut rng = fastrand::Rng::with_seed(7);
+        for _ in 0..500 {
+            let len = rng.usize(1..64);
+            let payload: Vec<u8> = (0..len).map(|_| rng.u8(..)).collect();
+            if std::str::from_utf8(&payload).is_ok() {
+                continue;
+            }
+            assert!(decompress(&payload).is_none(), "{:?}", payload);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_payloads() {
+        let large = vec![b'x'; MAX_DECOMPRESSED as usize + 1];
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = compression.compress(&large).unwrap();
+            assert!(compression.decompress(&compressed).is_err());
+            assert!(decompress(&compressed).is_none());
+        }
+        let fits = Compression::Gzip
+            .compress(&large[..MAX_DECOMPRESSED as usize])
+            .unwrap();
+        assert!(decompress(&fits).is_some());
+    }
+}
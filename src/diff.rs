@@ -0,0 +1,402 @@
+use egui::{Color32, Context, RichText};
+use serde_json::Value;
+
+use crate::message_buffer::ReceivedMessage;
+
+/// Above this many lines per side the text diff compares line by line instead of
+/// searching the longest common subsequence.
+const MAX_LCS_LINES: usize = 2000;
+const HEX_ROW: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum DiffMode {
+    #[default]
+    Json,
+    Text,
+    Hex,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Change {
+    Same,
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Clone)]
+pub struct DiffLine {
+    pub change: Change,
+    pub text: String,
+}
+
+impl DiffLine {
+    fn new(change: Change, text: String) -> Self {
+        DiffLine { change, text }
+    }
+}
+
+/// Changed, added and removed values by their JSON path.
+pub fn json_diff(old: &Value, new: &Value) -> Vec<DiffLine> {
+    let mut lines = vec![];
+    json_walk("$", old, new, &mut lines);
+    lines
+}
+
+fn json_walk(path: &str, old: &Value, new: &Value, lines: &mut Vec<DiffLine>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = format!("{}.{}", path, key);
+                match new.get(key) {
+                    Some(new_value) => json_walk(&path, old_value, new_value, lines),
+                    None => lines.push(DiffLine::new(
+                        Change::Removed,
+                        format!("{}: {}", path, old_value),
+                    )),
+                }
+            }
+            for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                lines.push(DiffLine::new(
+                    Change::Added,
+                    format!("{}.{}: {}", path, key, new_value),
+                ));
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for i in 0..old.len().max(new.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (old.get(i), new.get(i)) {
+                    (Some(old_value), Some(new_value)) => {
+                        json_walk(&path, old_value, new_value, lines)
+                    }
+                    (Some(old_value), None) => lines.push(DiffLine::new(
+                        Change::Removed,
+                        format!("{}: {}", path, old_value),
+                    )),
+                    (None, Some(new_value)) => lines.push(DiffLine::new(
+                        Change::Added,
+                        format!("{}: {}", path, new_value),
+                    )),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old == new => lines.push(DiffLine::new(Change::Same, format!("{}: {}", path, new))),
+        _ => lines.push(DiffLine::new(
+            Change::Changed,
+            format!("{}: {} → {}", path, old, new),
+        )),
+    }
+}
+
+/// Line diff based on the longest common subsequence.
+pub fn text_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    if old.len() > MAX_LCS_LINES || new.len() > MAX_LCS_LINES {
+        return pairwise_diff(&old, &new);
+    }
+    // lengths of the common subsequences of the suffixes
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::new(Change::Same, old[i].to_owned()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(DiffLine::new(Change::Added, new[j].to_owned()));
+            j += 1;
+        } else {
+            lines.push(DiffLine::new(Change::Removed, old[i].to_owned()));
+            i += 1;
+        }
+    }
+    lines
+}
+
+fn pairwise_diff(old: &[&str], new: &[&str]) -> Vec<DiffLine> {
+    let mut lines = vec![];
+    for i in 0..old.len().max(new.len()) {
+        match (old.get(i), new.get(i)) {
+            (Some(old), Some(new)) if old == new => {
+                lines.push(DiffLine::new(Change::Same, old.to_string()))
+            }
+            (old, new) => {
+                if let Some(old) = old {
+                    lines.push(DiffLine::new(Change::Removed, old.to_string()));
+                }
+                if let Some(new) = new {
+                    lines.push(DiffLine::new(Change::Added, new.to_string()));
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// Rows of 16 bytes, old and new side by side.
+pub fn hex_diff(old: &[u8], new: &[u8]) -> Vec<DiffLine> {
+    let hex = |row: Option<&[u8]>| match row {
+        Some(row) => row
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => String::new(),
+    };
+    let rows = old.len().max(new.len()).div_ceil(HEX_ROW);
+    (0..rows)
+        .map(|i| {
+            let offset = i * HEX_ROW;
+            let old_row = old.get(offset..(offset + HEX_ROW).min(old.len()));
+            let new_row = new.get(offset..(offset + HEX_ROW).min(new.len()));
+            let change = match (old_row, new_row) {
+                (Some(a), Some(b)) if a == b => Change::Same,
+                (Some(_), Some(_)) => Change::Changed,
+                (Some(_), None) => Change::Removed,
+                _ => Change::Added,
+            };
+            let text = format!("{:08x}  {:<47} | {}", offset, hex(old_row), hex(new_row));
+            DiffLine::new(change, text)
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct Snapshot {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+/// Comparison of two messages, shown in its own window.
+#[derive(Clone, Default)]
+pub struct DiffView {
+    pub open: bool,
+    old: Option<Snapshot>,
+    new: Option<Snapshot>,
+    mode: DiffMode,
+    show_unchanged: bool,
+    lines: Vec<DiffLine>,
+}
+
+impl DiffView {
+    /// Compares the payloads of `old` and `new`, picking the richest mode both support.
+    pub fn compare(&mut self, old: &ReceivedMessage, new: &ReceivedMessage) {
+        let snapshot = |message: &ReceivedMessage| Snapshot {
+            topic: message.event.topic.clone(),
+            payload: message.event.payload.to_vec(),
+        };
+        let (old, new) = (snapshot(old), snapshot(new));
+        let json = |s: &Snapshot| serde_json::from_slice::<Value>(&s.payload).is_ok();
+        let text = |s: &Snapshot| std::str::from_utf8(&s.payload).is_ok();
+        self.mode = if json(&old) && json(&new) {
+            DiffMode::Json
+        } else if text(&old) && text(&new) {
+            DiffMode::Text
+        } else {
+            DiffMode::Hex
+        };
+        self.old = Some(old);
+        self.new = Some(new);
+        self.open = true;
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let (Some(old), Some(new)) = (&self.old, &self.new) else {
+            return;
+        };
+        let (old, new) = (&old.payload, &new.payload);
+        self.lines = match self.mode {
+            DiffMode::Json => match (
+                serde_json::from_slice::<Value>(old),
+                serde_json::from_slice::<Value>(new),
+            ) {
+                (Ok(old), Ok(new)) => json_diff(&old, &new),
+                _ => vec![DiffLine::new(
+                    Change::Changed,
+                    String::from("payloads are not both JSON"),
+                )],
+            },
+            DiffMode::Text => {
+                text_diff(&String::from_utf8_lossy(old), &String::from_utf8_lossy(new))
+            }
+            DiffMode::Hex => hex_diff(old, new),
+        };
+    }
+
+    pub fn window(&mut self, ctx: &Context, id: u32, name: &str) {
+        let mut open = self.open;
+        egui::Window::new(format!("Diff: {}", name))
+            .id(format!("diff_{}", id).into())
+            .open(&mut open)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                let (Some(old), Some(new)) = (&self.old, &self.new) else {
+                    return;
+                };
+                ui.small(format!("old: {} ({} bytes)", old.topic, old.payload.len()));
+                ui.small(format!("new: {} ({} bytes)", new.topic, new.payload.len()));
+                ui.horizontal(|ui| {
+                    let mode = self.mode;
+                    ui.selectable_value(&mut self.mode, DiffMode::Json, "JSON");
+                    ui.selectable_value(&mut self.mode, DiffMode::Text, "text");
+                    ui.selectable_value(&mut self.mode, DiffMode::Hex, "hex");
+                    ui.checkbox(&mut self.show_unchanged, "show unchanged");
+                    if mode != self.mode {
+                        self.update();
+                    }
+                });
+                let changes = self
+                    .lines
+                    .iter()
+                    .filter(|line| line.change != Change::Same)
+                    .count();
+                ui.small(format!("{} changes", changes));
+                ui.separator();
+                let lines: Vec<&DiffLine> = self
+                    .lines
+                    .iter()
+                    .filter(|line| self.show_unchanged || line.change != Change::Same)
+                    .collect();
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both().auto_shrink(false).show_rows(
+                    ui,
+                    row_height,
+                    lines.len(),
+                    |ui, range| {
+                        for line in &lines[range] {
+                            let (prefix, color) = match line.change {
+                                Change::Same => (" ", ui.visuals().weak_text_color()),
+                                Change::Added => ("+", Color32::from_rgb(80, 180, 80)),
+                                Change::Removed => ("-", Color32::from_rgb(220, 80, 80)),
+                                Change::Changed => ("~", Color32::from_rgb(220, 170, 60)),
+                            };
+                            ui.label(
+                                RichText::new(format!("{} {}", prefix, line.text))
+                                    .monospace()
+                                    .color(color),
+                            );
+                        }
+                    },
+                );
+            });
+        self.open = open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(lines: &[DiffLine]) -> Vec<(char, &str)> {
+        lines
+            .iter()
+            .map(|line| {
+                let change = match line.change {
+                    Change::Same => ' ',
+                    Change::Added => '+',
+                    Change::Removed => '-',
+                    Change::Changed => '~',
+                };
+                (change, line.text.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn json_keys() {
+        let old = serde_json::json!({"a": 1, "b": {"c": true}, "d": "x"});
+        let new = serde_json::json!({"a": 1, "b": {"c": false}, "e": null});
+        assert_eq!(
+            changes(&json_diff(&old, &new)),
+            [
+                (' ', "$.a: 1"),
+                ('~', "$.b.c: true → false"),
+                ('-', "$.d: \"x\""),
+                ('+', "$.e: null"),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_array_tails() {
+        let old = serde_json::json!([1, 2, 3]);
+        let new = serde_json::json!([1, 5]);
+        assert_eq!(
+            changes(&json_diff(&old, &new)),
+            [(' ', "$[0]: 1"), ('~', "$[1]: 2 → 5"), ('-', "$[2]: 3")]
+        );
+        assert_eq!(
+            changes(&json_diff(&new, &old)),
+            [(' ', "$[0]: 1"), ('~', "$[1]: 5 → 2"), ('+', "$[2]: 3")]
+        );
+        assert_eq!(
+            changes(&json_diff(&serde_json::json!(1), &serde_json::json!([1]))),
+            [('~', "$: 1 → [1]")]
+        );
+    }
+
+    #[test]
+    fn text_insertions_in_the_middle() {
+        assert_eq!(
+            changes(&text_diff("a\nb\nc\nd", "a\nx\ny\nc\nd\ne")),
+            [
+                (' ', "a"),
+                ('+', "x"),
+                ('+', "y"),
+                ('-', "b"),
+                (' ', "c"),
+                (' ', "d"),
+                ('+', "e"),
+            ]
+        );
+        assert!(text_diff("", "").is_empty());
+    }
+
+    #[test]
+    fn long_texts_compare_line_by_line() {
+        let old = (0..=MAX_LCS_LINES)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let new = format!("inserted\n{}", old);
+        let lines = text_diff(&old, &new);
+        // without the LCS every line after the insertion counts as changed
+        assert_eq!(lines.len(), 2 * (MAX_LCS_LINES + 1) + 1);
+        assert!(lines.iter().all(|line| line.change != Change::Same));
+    }
+
+    #[test]
+    fn hex_rows_of_unequal_length() {
+        let old: Vec<u8> = (0..20).collect();
+        let mut new = old.clone();
+        new[17] = 0xff;
+        new.extend_from_slice(&[0xaa; 16]);
+        let lines = hex_diff(&old, &new);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].change == Change::Same);
+        assert!(lines[1].change == Change::Changed);
+        assert!(lines[2].change == Change::Added);
+        assert_eq!(
+            lines[1].text,
+            format!(
+                "00000010  {:<47} | {}",
+                "10 11 12 13", "10 ff 12 13 aa aa aa aa aa aa aa aa aa aa aa aa"
+            )
+        );
+        assert!(lines[2].text.starts_with("00000020  "));
+        assert!(hex_diff(&new, &old)[2].change == Change::Removed);
+    }
+}
@@ -2,4 +2,27 @@
 
 mod app;
 pub use app::TemplateApp;
+mod bridge;
+mod compression;
+mod diff;
+mod loadtest;
+mod message_buffer;
 mod mqtt_servermanager;
+mod packet_log;
+mod payload_template;
+mod preview;
+mod probe;
+mod profiles;
+mod publish;
+mod request;
+mod retained;
+mod rules;
+mod scheduler;
+mod script;
+mod secrets;
+mod shared_test;
+mod simulator;
+mod subscription;
+mod sys_dashboard;
+mod topic;
+mod traffic;
@@ -0,0 +1,586 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use egui::Ui;
+use rumqttc::{Client, Event, Incoming, QoS};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::MqttServer,
+    mqtt_servermanager::{mqtt_options, MqttServerManager},
+    publish::{now_us, qos_combo},
+};
+
+/// Sequence number, publisher index and send time in microseconds.
+const HEADER_LEN: usize = 8 + 4 + 8;
+/// Time given to in-flight messages after the last publish.
+const DRAIN: Duration = Duration::from_secs(2);
+/// Sub-buckets per power of two of the latency histogram, about 6% resolution.
+const SUB_BUCKETS: u64 = 16;
+const BUCKETS: usize = (64 - 3) * SUB_BUCKETS as usize;
+/// Sequence numbers per publisher remembered to spot duplicates.
+const SEQ_WINDOW: u64 = 4096;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoadTestConfig {
+    pub publishers: u32,
+    pub subscribers: u32,
+    /// Messages per second over all publishers.
+    pub rate: u32,
+    pub payload_size: usize,
+    pub qos: u8,
+    /// Number of topics the messages are spread over.
+    pub topics: u32,
+    pub topic_prefix: String,
+    /// Zero runs until stopped.
+    pub duration_secs: u64,
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> Self {
+        LoadTestConfig {
+            publishers: 1,
+            subscribers: 1,
+            rate: 100,
+            payload_size: 64,
+            qos: 0,
+            topics: 1,
+            topic_prefix: String::from("oldqtt/load"),
+            duration_secs: 10,
+        }
+    }
+}
+
+#[derive(Default)]
+struct LoadStats {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    received: AtomicU64,
+    duplicates: AtomicU64,
+    subscribed: AtomicU32,
+    latencies_us: Mutex<Histogram>,
+}
+
+/// Latencies in microseconds, counted in log-linear buckets of fixed size.
+#[derive(Clone)]
+struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS {
+            return value as usize;
+        }
+        let exp = 63 - value.leading_zeros() as u64;
+        let sub = (value >> (exp - 4)) & (SUB_BUCKETS - 1);
+        ((exp - 3) * SUB_BUCKETS + sub) as usize
+    }
+
+    /// Smallest value counted in bucket `i`.
+    fn lower_bound(i: usize) -> u64 {
+        let i = i as u64;
+        if i < SUB_BUCKETS {
+            return i;
+        }
+        let exp = i / SUB_BUCKETS + 3;
+        (SUB_BUCKETS + i % SUB_BUCKETS) << (exp - 4)
+    }
+
+    fn record(&mut self, value: u64) {
+        self.counts[Self::bucket(value)] += 1;
+        self.total += 1;
+        self.max = self.max.max(value);
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        if p >= 1.0 {
+            return self.max;
+        }
+        let rank = ((self.total as f64 * p).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::lower_bound(i).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// The most recent sequence numbers received from one publisher.
+struct SeqWindow {
+    next: u64,
+    bits: Vec<u64>,
+}
+
+impl Default for SeqWindow {
+    fn default() -> Self {
+        SeqWindow {
+            next: 0,
+            bits: vec![0; (SEQ_WINDOW / 64) as usize],
+        }
+    }
+}
+
+impl SeqWindow {
+    /// Returns false for a sequence number seen before. Numbers too old to be
+    /// remembered count as new.
+    fn insert(&mut self, seq: u64) -> bool {
+        if seq >= self.next {
+            if seq - self.next >= SEQ_WINDOW {
+                self.bits.fill(0);
+            } else {
+                for skipped in self.next..seq {
+                    self.set(skipped, false);
+                }
+            }
+            self.set(seq, true);
+            self.next = seq + 1;
+            return true;
+        }
+        if self.next - seq > SEQ_WINDOW {
+            return true;
+        }
+        let (word, bit) = Self::position(seq);
+        let new = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        new
+    }
+
+    fn position(seq: u64) -> (usize, u64) {
+        let i = seq % SEQ_WINDOW;
+        ((i / 64) as usize, 1 << (i % 64))
+    }
+
+    fn set(&mut self, seq: u64, value: bool) {
+        let (word, bit) = Self::position(seq);
+        match value {
+            true => self.bits[word] |= bit,
+            false => self.bits[word] &= !bit,
+        }
+    }
+}
+
+#[derive(Serialize, Default, Clone)]
+pub struct LoadReport {
+    pub config: Option<LoadTestConfig>,
+    pub finished: bool,
+    pub elapsed_secs: f64,
+    pub sent: u64,
+    pub failed: u64,
+    pub received: u64,
+    pub duplicates: u64,
+    /// Expected but not (yet) received deliveries.
+    pub missing: u64,
+    pub sent_per_sec: f64,
+    pub received_per_sec: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p90_ms: f64,
+    pub latency_p99_ms: f64,
+    pub latency_max_ms: f64,
+}
+
+/// Simulated clients hammering a broker, stopped and disconnected when dropped.
+pub struct LoadTest {
+    config: LoadTestConfig,
+    stats: Arc<LoadStats>,
+    stop: Arc<AtomicBool>,
+    started: Instant,
+    coordinator: Option<JoinHandle<()>>,
+    finished_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl LoadTest {
+    pub fn start(id: u32, server: &MqttServer, config: LoadTestConfig) -> Self {
+        let stats = Arc::new(LoadStats::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let closed = Arc::new(AtomicBool::new(false));
+        let finished_at = Arc::new(Mutex::new(None));
+        let mut clients = vec![];
+        for i in 0..config.subscribers {
+            let options = mqtt_options(server, format!("oldqtt_{:x}_load_sub_{}", id, i));
+            let (client, mut connection) = Client::new(options, 100);
+            if let Err(e) = client.subscribe(format!("{}/#", config.topic_prefix), qos(config.qos))
+            {
+                log::error!("Load test subscriber {} cannot subscribe: {}", i, e);
+            }
+            let (stats, closed) = (stats.clone(), closed.clone());
+            std::thread::spawn(move || {
+                let mut seen: HashMap<u32, SeqWindow> = HashMap::new();
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Incoming(Incoming::SubAck(_))) => {
+                            stats.subscribed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(Event::Incoming(Incoming::Publish(message))) => {
+                            stats.received.fetch_add(1, Ordering::Relaxed);
+                            let Some((seq, publisher, sent_us)) = parse_header(&message.payload)
+                            else {
+                                continue;
+                            };
+                            if !seen.entry(publisher).or_default().insert(seq) {
+                                stats.duplicates.fetch_add(1, Ordering::Relaxed);
+                            }
+                            if let Ok(mut latencies) = stats.latencies_us.lock() {
+                                latencies.record(now_us().saturating_sub(sent_us));
+                            }
+                        }
+                        Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => return,
+                        Err(_) if closed.load(Ordering::Relaxed) => return,
+                        Err(e) => {
+                            log::error!("Load test subscriber error: {:?}", e);
+                            std::thread::sleep(Duration::from_secs(1));
+                        }
+                        _ => {}
+                    }
+                }
+            });
+            clients.push(client);
+        }
+        let mut publishers = vec![];
+        for i in 0..config.publishers {
+            let options = mqtt_options(server, format!("oldqtt_{:x}_load_pub_{}", id, i));
+            let (client, mut connection) = Client::new(options, 100);
+            let closed_c = closed.clone();
+            std::thread::spawn(move || {
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => return,
+                        Err(_) if closed_c.load(Ordering::Relaxed) => return,
+                        Err(e) => {
+                            log::error!("Load test publisher error: {:?}", e);
+                            std::thread::sleep(Duration::from_secs(1));
+                        }
+                        _ => {}
+                    }
+                }
+            });
+            let (config, stats, stop) = (config.clone(), stats.clone(), stop.clone());
+            let publisher = client.clone();
+            publishers.push(std::thread::spawn(move || {
+                publish_loop(i, publisher, &config, &stats, &stop)
+            }));
+            clients.push(client);
+        }
+        let (config_c, stop_c, finished_c) = (config.clone(), stop.clone(), finished_at.clone());
+        let coordinator = std::thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(config_c.duration_secs);
+            while !stop_c.load(Ordering::Relaxed)
+                && (config_c.duration_secs == 0 || Instant::now() < deadline)
+            {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            stop_c.store(true, Ordering::Relaxed);
+            for publisher in publishers {
+                let _ = publisher.join();
+            }
+            if let Ok(mut finished_at) = finished_c.lock() {
+                *finished_at = Some(Instant::now());
+            }
+            std::thread::sleep(DRAIN);
+            for client in clients {
+                let _ = client.disconnect();
+            }
+            closed.store(true, Ordering::Relaxed);
+        });
+        LoadTest {
+            config,
+            stats,
+            stop,
+            started: Instant::now(),
+            coordinator: Some(coordinator),
+            finished_at,
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.coordinator
+            .as_ref()
+            .is_some_and(|coordinator| !coordinator.is_finished())
+    }
+
+    pub fn report(&self) -> LoadReport {
+        let stats = &self.stats;
+        let finished_at = self.finished_at.lock().ok().and_then(|f| *f);
+        let elapsed = finished_at
+            .unwrap_or_else(Instant::now)
+            .duration_since(self.started)
+            .as_secs_f64()
+            .max(f64::EPSILON);
+        let sent = stats.sent.load(Ordering::Relaxed);
+        let received = stats.received.load(Ordering::Relaxed);
+        let duplicates = stats.duplicates.load(Ordering::Relaxed);
+        let latencies = stats
+            .latencies_us
+            .lock()
+            .map(|l| l.clone())
+            .unwrap_or_default();
+        let percentile = |p: f64| latencies.percentile(p) as f64 / 1000.0;
+        LoadReport {
+            config: Some(self.config.clone()),
+            finished: !self.is_running(),
+            elapsed_secs: elapsed,
+            sent,
+            failed: stats.failed.load(Ordering::Relaxed),
+            received,
+            duplicates,
+            missing: (sent * self.config.subscribers as u64).saturating_sub(received - duplicates),
+            sent_per_sec: sent as f64 / elapsed,
+            received_per_sec: received as f64 / elapsed,
+            latency_p50_ms: percentile(0.5),
+            latency_p90_ms: percentile(0.9),
+            latency_p99_ms: percentile(0.99),
+            latency_max_ms: percentile(1.0),
+        }
+    }
+}
+
+impl Drop for LoadTest {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn publish_loop(
+    index: u32,
+    client: Client,
+    config: &LoadTestConfig,
+    stats: &LoadStats,
+    stop: &AtomicBool,
+) {
+    // give subscribers a chance to be in place before the first message
+    let warmup = Instant::now() + Duration::from_secs(5);
+    while stats.subscribed.load(Ordering::Relaxed) < config.subscribers && Instant::now() < warmup {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let interval = Duration::from_secs_f64(config.publishers as f64 / config.rate.max(1) as f64);
+    let mut next = Instant::now();
+    let mut seq: u64 = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let mut payload = Vec::with_capacity(config.payload_size.max(HEADER_LEN));
+        payload.extend_from_slice(&seq.to_be_bytes());
+        payload.extend_from_slice(&index.to_be_bytes());
+        payload.extend_from_slice(&now_us().to_be_bytes());
+        payload.resize(config.payload_size.max(HEADER_LEN), b'x');
+        let topic = format!(
+            "{}/{}",
+            config.topic_prefix,
+            seq % config.topics.max(1) as u64
+        );
+        match client.publish(topic, qos(config.qos), false, payload) {
+            Ok(()) => stats.sent.fetch_add(1, Ordering::Relaxed),
+            Err(_) => stats.failed.fetch_add(1, Ordering::Relaxed),
+        };
+        seq += 1;
+        next += interval;
+        let now = Instant::now();
+        if next > now {
+            std::thread::sleep(next - now);
+        }
+    }
+}
+
+fn parse_header(payload: &[u8]) -> Option<(u64, u32, u64)> {
+    let seq = u64::from_be_bytes(payload.get(0..8)?.try_into().ok()?);
+    let publisher = u32::from_be_bytes(payload.get(8..12)?.try_into().ok()?);
+    let sent_us = u64::from_be_bytes(payload.get(12..20)?.try_into().ok()?);
+    Some((seq, publisher, sent_us))
+}
+
+fn qos(qos: u8) -> QoS {
+    rumqttc::qos(qos).unwrap_or(QoS::AtMostOnce)
+}
+
+pub fn export(path: &str, report: &LoadReport) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(report)?)?;
+    Ok(())
+}
+
+#[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoadTestPanel {
+    config: LoadTestConfig,
+    report_path: String,
+    #[serde(skip)]
+    status: String,
+}
+
+impl LoadTestPanel {
+    /// Shows config and live report, returns the config when a test should start.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        id: u32,
+        manager: &mut MqttServerManager,
+    ) -> Option<LoadTestConfig> {
+        let mut start = None;
+        ui.collapsing("Load test", |ui| {
+            let running = manager.load_test(id).is_some_and(LoadTest::is_running);
+            ui.add_enabled_ui(!running, |ui| {
+                let config = &mut self.config;
+                egui::Grid::new("load_test_config").show(ui, |ui| {
+                    ui.label("publishers");
+                    ui.add(egui::DragValue::new(&mut config.publishers).range(1..=1000));
+                    ui.label("subscribers");
+                    ui.add(egui::DragValue::new(&mut config.subscribers).range(0..=1000));
+                    ui.end_row();
+                    ui.label("rate");
+                    ui.add(
+                        egui::DragValue::new(&mut config.rate)
+                            .range(1..=1_000_000)
+                            .suffix(" msg/s"),
+                    );
+                    ui.label("payload");
+                    ui.add(
+                        egui::DragValue::new(&mut config.payload_size)
+                            .range(HEADER_LEN..=6_000_000)
+                            .suffix(" B"),
+                    );
+                    ui.end_row();
+                    ui.label("topics");
+                    ui.add(egui::DragValue::new(&mut config.topics).range(1..=100_000));
+                    ui.label("QoS");
+                    qos_combo(ui, "load_qos", &mut config.qos);
+                    ui.end_row();
+                    ui.label("prefix");
+                    ui.text_edit_singleline(&mut config.topic_prefix);
+                    ui.label("duration");
+                    ui.add(egui::DragValue::new(&mut config.duration_secs).suffix(" s"));
+                    ui.end_row();
+                });
+            });
+            ui.horizontal(|ui| {
+                if running {
+                    if ui.button("stop").clicked() {
+                        manager.stop_load_test(id);
+                    }
+                } else if ui
+                    .add_enabled(!running, egui::Button::new("start"))
+                    .clicked()
+                {
+                    start = Some(self.config.clone());
+                }
+            });
+            if let Some(load_test) = manager.load_test(id) {
+                let report = load_test.report();
+                ui.ctx().request_repaint_after(Duration::from_millis(500));
+                egui::Grid::new("load_test_report").show(ui, |ui| {
+                    ui.label("elapsed");
+                    ui.label(format!("{:.1} s", report.elapsed_secs));
+                    ui.label("sent / failed");
+                    ui.label(format!("{} / {}", report.sent, report.failed));
+                    ui.end_row();
+                    ui.label("throughput");
+                    ui.label(format!(
+                        "{:.0} out, {:.0} in msg/s",
+                        report.sent_per_sec, report.received_per_sec
+                    ));
+                    ui.label("received");
+                    ui.label(report.received.to_string());
+                    ui.end_row();
+                    ui.label("missing / dup");
+                    ui.label(format!("{} / {}", report.missing, report.duplicates));
+                    ui.label("latency");
+                    ui.label(format!(
+                        "p50 {:.1} p90 {:.1} p99 {:.1} max {:.1} ms",
+                        report.latency_p50_ms,
+                        report.latency_p90_ms,
+                        report.latency_p99_ms,
+                        report.latency_max_ms
+                    ));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.report_path)
+                            .hint_text("load_report.json"),
+                    );
+                    if ui.button("export report").clicked() {
+                        self.status = match export(&self.report_path, &report) {
+                            Ok(()) => String::from("report written"),
+                            Err(e) => format!("export failed: {}", e),
+                        };
+                    }
+                });
+                if !self.status.is_empty() {
+                    ui.small(&self.status);
+                }
+            }
+        });
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_contiguous() {
+        for i in 0..BUCKETS - 1 {
+            let bound = Histogram::lower_bound(i + 1);
+            assert_eq!(Histogram::bucket(bound), i + 1);
+            assert_eq!(Histogram::bucket(bound - 1), i);
+        }
+        assert_eq!(Histogram::bucket(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.percentile(0.5), 0);
+        for us in 1..=1000 {
+            histogram.record(us);
+        }
+        let p50 = histogram.percentile(0.5);
+        assert!((470..=500).contains(&p50), "{}", p50);
+        let p99 = histogram.percentile(0.99);
+        assert!((930..=990).contains(&p99), "{}", p99);
+        assert_eq!(histogram.percentile(1.0), 1000);
+    }
+
+    #[test]
+    fn seq_window_spots_duplicates() {
+        let mut window = SeqWindow::default();
+        assert!(window.insert(0));
+        assert!(window.insert(2));
+        assert!(!window.insert(2));
+        // out of order but not seen yet
+        assert!(window.insert(1));
+        assert!(!window.insert(0));
+        // a jump past the window forgets everything before it
+        assert!(window.insert(2 + SEQ_WINDOW * 2));
+        assert!(window.insert(2 + SEQ_WINDOW));
+        assert!(!window.insert(2 + SEQ_WINDOW * 2));
+        // too old to tell
+        assert!(window.insert(2));
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::{HashMap, VecDeque};
+
+use rumqttc::Publish;
+
+use crate::compression::{self, Compression};
+
+/// An incoming message with the filters of the subscriptions it arrived through.
+#[derive(Clone)]
+pub struct ReceivedMessage {
+    pub event: Publish,
+    pub subscriptions: Vec<String>,
+    /// Position in the order of arrival, unaffected by evictions.
+    pub seq: u64,
+    /// Compression and size on the wire of payloads that were decompressed.
+    pub compressed: Option<(Compression, usize)>,
+}
+
+impl ReceivedMessage {
+    /// Bytes accounted to the buffer, topic and payload.
+    fn size(&self) -> usize {
+        self.event.topic.len() + self.event.payload.len()
+    }
+}
+
+pub struct BufferLimits {
+    pub max_messages: usize,
+    pub max_bytes: usize,
+    /// Messages kept per topic, zero keeps all.
+    pub keep_per_topic: usize,
+}
+
+/// Flags over a growing list of slots, counting and finding the set ones.
+#[derive(Clone)]
+struct Counts {
+    /// Fenwick tree, one-based.
+    tree: Vec<usize>,
+}
+
+impl Default for Counts {
+    fn default() -> Self {
+        Counts { tree: vec![0] }
+    }
+}
+
+impl Counts {
+    /// Appends a set flag.
+    fn push(&mut self) {
+        let i = self.tree.len();
+        let covered = i - (i & i.wrapping_neg());
+        let sum = 1 + self.prefix(i - 1) - self.prefix(covered);
+        self.tree.push(sum);
+    }
+
+    fn unset(&mut self, i: usize) {
+        let mut i = i + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Set flags among the first `n`.
+    fn prefix(&self, n: usize) -> usize {
+        let (mut i, mut sum) = (n, 0);
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+
+    /// Position of the set flag with `n` set flags before it.
+    fn select(&self, n: usize) -> usize {
+        let (mut pos, mut rest) = (0, n + 1);
+        let mut step = (self.tree.len() - 1)
+            .checked_ilog2()
+            .map_or(0, |log| 1 << log);
+        while step > 0 {
+            if pos + step < self.tree.len() && self.tree[pos + step] < rest {
+                pos += step;
+                rest -= self.tree[pos];
+            }
+            step >>= 1;
+        }
+        pos
+    }
+}
+
+/// Received messages of a server, oldest first, bounded by count, bytes and per topic.
+///
+/// Evicted messages leave an empty slot behind, the slots are compacted once they
+/// outnumber the messages.
+#[derive(Default, Clone)]
+pub struct MessageBuffer {
+    slots: Vec<Option<ReceivedMessage>>,
+    /// Sequence numbers of the slots.
+    seqs: Vec<u64>,
+    /// Which slots hold a message.
+    live: Counts,
+    /// First slot that may hold a message.
+    head: usize,
+    len: usize,
+    bytes: usize,
+    /// Sequence numbers of the buffered messages of each topic, oldest first.
+    topics: HashMap<String, VecDeque<u64>>,
+    received: u64,
+}
+
+impl MessageBuffer {
+    /// Stores a message, with its payload decompressed when `decompress` is set.
+    pub fn push(
+        &mut self,
+        mut event: Publish,
+        subscriptions: Vec<String>,
+        decompress: bool,
+        limits: &BufferLimits,
+    ) {
+        let mut compressed = None;
+        if let Some((compression, payload)) = decompress
+            .then(|| compression::decompress(&event.payload))
+            .flatten()
+        {
+            compressed = Some((compression, event.payload.len()));
+            event.payload = payload.into();
+        }
+        let message = ReceivedMessage {
+            event,
+            subscriptions,
+            seq: self.received,
+            compressed,
+        };
+        self.received += 1;
+        let seqs = self.topics.entry(message.event.topic.clone()).or_default();
+        seqs.push_back(message.seq);
+        let evicted = match limits.keep_per_topic {
+            0 => None,
+            keep if seqs.len() > keep => seqs.pop_front(),
+            _ => None,
+        };
+        self.len += 1;
+        self.bytes += message.size();
+        self.seqs.push(message.seq);
+        self.slots.push(Some(message));
+        self.live.push();
+        if let Some(seq) = evicted {
+            if let Some(i) = self.slot(seq) {
+                self.take(i);
+            }
+        }
+        while self.len > limits.max_messages || self.bytes > limits.max_bytes {
+            while self.slots.get(self.head).is_some_and(Option::is_none) {
+                self.head += 1;
+            }
+            let Some(message) = self.take(self.head) else {
+                break;
+            };
+            let topic = &message.event.topic;
+            if let Some(seqs) = self.topics.get_mut(topic) {
+                seqs.pop_front();
+                if seqs.is_empty() {
+                    self.topics.remove(topic);
+                }
+            }
+        }
+        if self.slots.len() > 2 * self.len + 1024 {
+            self.compact();
+        }
+    }
+
+    fn slot(&self, seq: u64) -> Option<usize> {
+        self.seqs.binary_search(&seq).ok()
+    }
+
+    fn take(&mut self, i: usize) -> Option<ReceivedMessage> {
+        let message = self.slots.get_mut(i)?.take()?;
+        self.live.unset(i);
+        self.len -= 1;
+        self.bytes -= message.size();
+        Some(message)
+    }
+
+    fn compact(&mut self) {
+        let slots = std::mem::take(&mut self.slots);
+        self.seqs.clear();
+        self.live = Counts::default();
+        self.head = 0;
+        for message in slots.into_iter().flatten() {
+            self.seqs.push(message.seq);
+            self.slots.push(Some(message));
+            self.live.push();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = MessageBuffer {
+            received: self.received,
+            ..MessageBuffer::default()
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn topic_count(&self) -> usize {
+        self.topics.len()
+    }
+
+    /// Messages pushed so far, including evicted ones.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    pub fn get(&self, i: usize) -> Option<&ReceivedMessage> {
+        if i >= self.len {
+            return None;
+        }
+        self.slots.get(self.live.select(i))?.as_ref()
+    }
+
+    pub fn find(&self, seq: u64) -> Option<&ReceivedMessage> {
+        self.slots.get(self.slot(seq)?)?.as_ref()
+    }
+
+    /// The buffered message on the same topic that arrived right before `seq`.
+    pub fn previous_on_topic(&self, seq: u64) -> Option<&ReceivedMessage> {
+        let seqs = self.topics.get(&self.find(seq)?.event.topic)?;
+        let i = seqs.partition_point(|s| *s < seq).checked_sub(1)?;
+        self.find(seqs[i])
+    }
+
+    /// Number of buffered messages that arrived before `seq`.
+    pub fn count_before(&self, seq: u64) -> usize {
+        self.live.prefix(self.seqs.partition_point(|s| *s < seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_messages: usize, max_bytes: usize, keep_per_topic: usize) -> BufferLimits {
+        BufferLimits {
+            max_messages,
+            max_bytes,
+            keep_per_topic,
+        }
+    }
+
+    fn push(buffer: &mut MessageBuffer, topic: &str, payload: &str, limits: &BufferLimits) {
+        let event = Publish::new(topic, rumqttc::QoS::AtMostOnce, payload);
+        buffer.push(event, vec![], false, limits);
+    }
+
+    fn seqs(buffer: &MessageBuffer) -> Vec<u64> {
+        (0..buffer.len())
+            .map(|i| buffer.get(i).unwrap().seq)
+            .collect()
+    }
+
+    #[test]
+    fn evicts_oldest_by_count() {
+        let (mut buffer, limits) = (MessageBuffer::default(), limits(3, usize::MAX, 0));
+        for i in 0..5 {
+            push(&mut buffer, &format!("t/{}", i), "x", &limits);
+        }
+        assert_eq!(seqs(&buffer), [2, 3, 4]);
+        assert_eq!(buffer.received(), 5);
+        assert_eq!(buffer.topic_count(), 3);
+        assert!(buffer.find(1).is_none());
+        assert_eq!(buffer.count_before(4), 2);
+        assert_eq!(buffer.count_before(0), 0);
+    }
+
+    #[test]
+    fn evicts_oldest_by_bytes() {
+        // topic and payload count, 4 bytes per message
+        let (mut buffer, limits) = (MessageBuffer::default(), limits(100, 10, 0));
+        for _ in 0..3 {
+            push(&mut buffer, "ab", "cd", &limits);
+        }
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.bytes(), 8);
+        push(&mut buffer, "ab", "0123456789", &limits);
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.bytes(), 0);
+        assert_eq!(buffer.topic_count(), 0);
+    }
+
+    #[test]
+    fn keeps_last_per_topic() {
+        let (mut buffer, limits) = (MessageBuffer::default(), limits(100, usize::MAX, 2));
+        for topic in ["a", "b", "a", "a", "b", "c", "a"] {
+            push(&mut buffer, topic, "xy", &limits);
+        }
+        assert_eq!(seqs(&buffer), [1, 3, 4, 5, 6]);
+        assert_eq!(buffer.bytes(), 5 * 3);
+        assert_eq!(buffer.topic_count(), 3);
+        assert_eq!(buffer.count_before(5), 3);
+        assert_eq!(buffer.previous_on_topic(6).unwrap().seq, 3);
+        assert_eq!(buffer.previous_on_topic(4).unwrap().seq, 1);
+        assert!(buffer.previous_on_topic(5).is_none());
+    }
+
+    #[test]
+    fn compacts_evicted_slots() {
+        let (mut buffer, limits) = (MessageBuffer::default(), limits(10_000, usize::MAX, 1));
+        push(&mut buffer, "old", "x", &limits);
+        for i in 0..5000 {
+            push(&mut buffer, &format!("t/{}", i % 3), "x", &limits);
+        }
+        assert!(buffer.slots.len() < 2 * buffer.len() + 1024);
+        assert_eq!(seqs(&buffer), [0, 4998, 4999, 5000]);
+        assert_eq!(buffer.count_before(4999), 2);
+        assert_eq!(buffer.bytes(), 4 + 3 * 4);
+        buffer.clear();
+        assert_eq!(
+            (buffer.len(), buffer.bytes(), buffer.received()),
+            (0, 0, 5001)
+        );
+        assert!(buffer.get(0).is_none());
+    }
+}
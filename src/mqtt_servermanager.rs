@@ -1,14 +1,40 @@
 use std::{
     collections::{HashMap, HashSet},
     error::Error,
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
     thread::JoinHandle,
+    time::Duration,
 };
 
 use egui::Context;
-use rumqttc::{Client, Event, Incoming, MqttOptions, Outgoing, Publish};
+use rumqttc::{Client, ClientError, Event, Incoming, MqttOptions, Outgoing, Publish, QoS};
 
-use crate::app::MqttServer;
+use crate::{
+    app::MqttServer,
+    bridge::{Bridge, BridgeConfig, LoopGuard},
+    compression::Compression,
+    loadtest::{LoadTest, LoadTestConfig},
+    packet_log::PacketLog,
+    probe::ProbeState,
+    publish::PublishTemplate,
+    request::PendingRequest,
+    retained::RetainedScan,
+    rules::RuleEngine,
+    scheduler::{RunningSchedule, Schedule, ScheduledPublish},
+    script::{ScriptConfig, ScriptRuntime},
+    shared_test::{SharedTest, SharedTestConfig},
+    simulator::{RunningSimulator, SimulatorDefinition},
+    subscription::{SubStatus, SubTracker, Subscription},
+    sys_dashboard::SysState,
+    topic,
+};
+
+/// Interval of the latency probe messages.
+const PROBE_INTERVAL_MS: u64 = 1000;
 
 #[derive(Clone)]
 pub struct MqttServerManagerEvent {
@@ -18,6 +44,15 @@ pub struct MqttServerManagerEvent {
 
 pub struct MqttServerManager {
     servers: HashMap<u32, Server>,
+    schedules: HashMap<(u32, u32), RunningSchedule>,
+    load_tests: HashMap<u32, LoadTest>,
+    retained_scans: HashMap<u32, RetainedScan>,
+    shared_tests: HashMap<u32, SharedTest>,
+    bridges: HashMap<u32, Bridge>,
+    loop_guard: LoopGuard,
+    scripts: HashMap<u32, ScriptRuntime>,
+    rules: RuleEngine,
+    simulators: HashMap<u32, RunningSimulator>,
     channel_tx: Sender<MqttServerManagerEvent>,
     channel_rx: Receiver<MqttServerManagerEvent>,
 }
@@ -28,6 +63,15 @@ impl MqttServerManager {
         let (channel_tx, channel_rx) = std::sync::mpsc::channel::<MqttServerManagerEvent>();
         MqttServerManager {
             servers,
+            schedules: HashMap::new(),
+            load_tests: HashMap::new(),
+            retained_scans: HashMap::new(),
+            shared_tests: HashMap::new(),
+            bridges: HashMap::new(),
+            loop_guard: LoopGuard::default(),
+            scripts: HashMap::new(),
+            rules: RuleEngine::default(),
+            simulators: HashMap::new(),
             channel_tx,
             channel_rx,
         }
@@ -45,84 +89,361 @@ impl MqttServerManager {
         &mut self.servers
     }
 
-    pub fn pull_events(&self) -> Vec<MqttServerManagerEvent> {
+    pub fn pull_events(&mut self) -> Vec<MqttServerManagerEvent> {
         let mut events = vec![];
         while let Ok(event) = self.channel_rx.try_recv() {
+            // simulated devices only talk to themselves
+            if self.simulators.values().any(|sim| sim.handle(&event)) {
+                continue;
+            }
+            self.forward(&event);
+            self.rules.evaluate(event.client, &event.event);
+            for script in self.scripts.values_mut() {
+                if script.server == event.client {
+                    script.dispatch(&event.event);
+                }
+            }
             events.push(event);
         }
+        self.run_scripts();
+        let connected: Vec<u32> = self.servers.keys().copied().collect();
+        self.rules.check_silence(&connected);
         events
     }
 
+    /// Runs due script timers and sends what the scripts published.
+    fn run_scripts(&mut self) {
+        for script in self.scripts.values_mut() {
+            script.tick();
+            let outbox = script.take_outbox();
+            let Some(server) = self.servers.get(&script.server) else {
+                if !outbox.is_empty() {
+                    script.log(format!("not connected, dropped {} messages", outbox.len()));
+                }
+                continue;
+            };
+            for (topic, payload, qos, retain) in outbox {
+                if let Err(e) = server.client().publish(topic, qos, retain, payload) {
+                    script.log(format!("error: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Publishes `event` on the targets of all bridges it matches.
+    fn forward(&mut self, event: &MqttServerManagerEvent) {
+        let message = &event.event;
+        let matching = self.bridges.values_mut().filter(|bridge| {
+            bridge.config.enabled
+                && bridge.config.source == event.client
+                && topic::matches(&bridge.config.filter, &message.topic)
+                && bridge.config.validate_prefixes().is_ok()
+        });
+        if self.loop_guard.is_forwarded(event.client, message) {
+            matching.for_each(|bridge| bridge.suppressed += 1);
+            return;
+        }
+        for bridge in matching {
+            let config = &bridge.config;
+            let Some(target) = self.servers.get(&config.target) else {
+                continue;
+            };
+            let topic = config.rewrite(&message.topic);
+            let qos = config
+                .qos
+                .and_then(|qos| rumqttc::qos(qos).ok())
+                .unwrap_or(message.qos);
+            match target.client().publish(
+                topic.clone(),
+                qos,
+                message.retain,
+                message.payload.to_vec(),
+            ) {
+                Ok(()) => {
+                    self.loop_guard
+                        .remember(config.target, &topic, &message.payload);
+                    bridge.forwarded += 1;
+                }
+                Err(e) => log::error!("Bridge '{:x}' cannot forward '{}': {}", config.id, topic, e),
+            }
+        }
+    }
+
     pub fn connect(&mut self, id: u32, server: &MqttServer, ctx: Context) {
-        let port: u16 = server.port();
-        let host: String = server.host();
-        let mqtt_server = Server::connect(host, port, self.channel(), id, ctx);
+        let options = mqtt_options(server, format!("oldqtt_{}", id));
+        let mqtt_server = Server::connect(options, self.channel(), id, ctx);
         self.servers_mut().insert(id, mqtt_server);
     }
 
     pub fn disconnect(&mut self, id: u32) {
+        self.schedules.retain(|(server_id, _), _| *server_id != id);
         if let Some(server) = self.servers_mut().remove(&id) {
             if let Err(e) = server.client().disconnect() {
                 log::error!("Cannot disconnect client '{}': '{}'", id, e);
             }
         }
     }
+
+    pub fn start_schedule(&mut self, id: u32, scheduled: &ScheduledPublish) -> Result<(), String> {
+        let Some(server) = self.servers.get(&id) else {
+            return Err(String::from("not connected"));
+        };
+        let running = RunningSchedule::start(server.publisher().clone(), scheduled.clone())?;
+        self.schedules.insert((id, scheduled.id), running);
+        Ok(())
+    }
+
+    pub fn stop_schedule(&mut self, id: u32, schedule_id: u32) {
+        if let Some(running) = self.schedules.get_mut(&(id, schedule_id)) {
+            running.stop();
+        }
+    }
+
+    pub fn remove_schedule(&mut self, id: u32, schedule_id: u32) {
+        self.schedules.remove(&(id, schedule_id));
+    }
+
+    pub fn schedule(&self, id: u32, schedule_id: u32) -> Option<&RunningSchedule> {
+        self.schedules.get(&(id, schedule_id))
+    }
+
+    /// Starts a load test against `server` with its own clients, replacing a previous one.
+    pub fn start_load_test(&mut self, id: u32, server: &MqttServer, config: LoadTestConfig) {
+        self.load_tests
+            .insert(id, LoadTest::start(id, server, config));
+    }
+
+    pub fn stop_load_test(&mut self, id: u32) {
+        if let Some(load_test) = self.load_tests.get(&id) {
+            load_test.stop();
+        }
+    }
+
+    pub fn load_test(&self, id: u32) -> Option<&LoadTest> {
+        self.load_tests.get(&id)
+    }
+
+    /// Scans the retained messages below `filter`, replacing the results of a previous scan.
+    pub fn start_retained_scan(&mut self, id: u32, server: &MqttServer, filter: String) {
+        self.retained_scans
+            .insert(id, RetainedScan::start(id, server, filter));
+    }
+
+    pub fn retained_scan(&self, id: u32) -> Option<&RetainedScan> {
+        self.retained_scans.get(&id)
+    }
+
+    /// Starts clients sharing one subscription on `server`, replacing a previous test.
+    pub fn start_shared_test(&mut self, id: u32, server: &MqttServer, config: SharedTestConfig) {
+        self.shared_tests
+            .insert(id, SharedTest::start(id, server, config));
+    }
+
+    pub fn stop_shared_test(&mut self, id: u32) {
+        self.shared_tests.remove(&id);
+    }
+
+    pub fn shared_test(&self, id: u32) -> Option<&SharedTest> {
+        self.shared_tests.get(&id)
+    }
+
+    /// Takes over the bridge configs, keeping the counters of unchanged bridges.
+    pub fn sync_bridges(&mut self, configs: &[BridgeConfig]) {
+        self.bridges
+            .retain(|id, _| configs.iter().any(|config| config.id == *id));
+        for config in configs {
+            match self.bridges.get_mut(&config.id) {
+                Some(bridge) if bridge.config == *config => {}
+                Some(bridge) => bridge.config = config.clone(),
+                None => {
+                    self.bridges.insert(
+                        config.id,
+                        Bridge {
+                            config: config.clone(),
+                            forwarded: 0,
+                            suppressed: 0,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn bridge(&self, id: u32) -> Option<&Bridge> {
+        self.bridges.get(&id)
+    }
+
+    /// Filters `id` has to subscribe to on behalf of bridges, running scripts and rules.
+    pub fn internal_filters(&self, id: u32) -> Vec<String> {
+        let bridges = self
+            .bridges
+            .values()
+            .filter(|bridge| bridge.config.enabled && bridge.config.source == id)
+            .map(|bridge| bridge.config.filter.clone());
+        let scripts = self
+            .scripts
+            .values()
+            .filter(|script| script.server == id)
+            .flat_map(ScriptRuntime::filters);
+        bridges
+            .chain(scripts)
+            .chain(self.rules.filters(id))
+            .collect()
+    }
+
+    /// Runs `config`, replacing a running instance of the same script.
+    pub fn start_script(&mut self, config: &ScriptConfig) {
+        self.scripts.insert(config.id, ScriptRuntime::start(config));
+    }
+
+    pub fn stop_script(&mut self, id: u32) {
+        self.scripts.remove(&id);
+    }
+
+    pub fn script(&self, id: u32) -> Option<&ScriptRuntime> {
+        self.scripts.get(&id)
+    }
+
+    /// Connects the instances of `definition` to `server`, replacing running ones.
+    pub fn start_simulator(
+        &mut self,
+        definition: &SimulatorDefinition,
+        server: &MqttServer,
+        ctx: &Context,
+    ) {
+        let running = RunningSimulator::start(definition, server, self.channel(), ctx);
+        self.simulators.insert(definition.id, running);
+    }
+
+    pub fn stop_simulator(&mut self, id: u32) {
+        self.simulators.remove(&id);
+    }
+
+    pub fn simulator(&self, id: u32) -> Option<&RunningSimulator> {
+        self.simulators.get(&id)
+    }
+
+    pub fn rules(&self) -> &RuleEngine {
+        &self.rules
+    }
+
+    pub fn rules_mut(&mut self) -> &mut RuleEngine {
+        &mut self.rules
+    }
+
+    pub fn has_script_timers(&self) -> bool {
+        self.scripts.values().any(ScriptRuntime::has_timers)
+    }
+}
+
+/// Connection options for `server`, shared by all clients connecting to it.
+pub fn mqtt_options(server: &MqttServer, client_id: String) -> MqttOptions {
+    let mut options = MqttOptions::new(client_id, server.host(), server.port());
+    options.set_max_packet_size(6000000, 6000000);
+    if let Some((username, password)) = server.credentials() {
+        options.set_credentials(username, password);
+    }
+    options
 }
 
 pub struct Server {
     id: u32,
+    options: MqttOptions,
     client: rumqttc::Client,
     handle: JoinHandle<()>,
     current_subs: HashSet<String>,
+    internal_subs: HashSet<String>,
+    publisher: Publisher,
+    probe: Arc<ProbeState>,
+    sys: Arc<SysState>,
+    probe_publisher: Option<RunningSchedule>,
+    packets: Arc<PacketLog>,
+    sub_tracker: Arc<SubTracker>,
 }
 
 impl Server {
-    pub fn connect<S>(
-        host: S,
-        port: u16,
+    pub fn connect(
+        options: MqttOptions,
         channel: Sender<MqttServerManagerEvent>,
         id: u32,
         ctx: Context,
-    ) -> Self
-    where
-        S: Into<String> + Clone,
-    {
-        let mut options = MqttOptions::new(format!("oldqtt_{}", id), host.clone(), port);
-        options.set_max_packet_size(6000000, 6000000);
-        let (client, connection) = Client::new(options, 20);
+    ) -> Self {
+        let (client, connection) = Client::new(options.clone(), 20);
         let channel_c = channel.clone();
+        let probe = Arc::new(ProbeState::new(id));
+        let probe_c = probe.clone();
+        let sys = Arc::new(SysState::new());
+        let sys_c = sys.clone();
+        let packets = Arc::new(PacketLog::new(options.client_id()));
+        let packets_c = packets.clone();
+        let sub_tracker = Arc::new(SubTracker::default());
+        let sub_tracker_c = sub_tracker.clone();
         let handle = std::thread::spawn(move || {
             log::info!("MQTT Event Loop started.");
-            Self::poll_iter(connection, channel_c, id, ctx);
+            Self::poll_iter(
+                connection,
+                channel_c,
+                id,
+                ctx,
+                probe_c,
+                sys_c,
+                packets_c,
+                sub_tracker_c,
+            );
             log::info!("MQTT Event Loop ended.");
         });
         Server {
             id,
-            client,
+            options,
+            client: client.clone(),
             handle,
             current_subs: HashSet::new(),
+            internal_subs: HashSet::new(),
+            publisher: Publisher::new(id, client),
+            probe,
+            probe_publisher: None,
+            sys,
+            packets,
+            sub_tracker,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn poll_iter(
         mut connection: rumqttc::Connection,
         channel: Sender<MqttServerManagerEvent>,
         id: u32,
         ctx: Context,
+        probe: Arc<ProbeState>,
+        sys: Arc<SysState>,
+        packets: Arc<PacketLog>,
+        sub_tracker: Arc<SubTracker>,
     ) {
         loop {
             for event in connection.iter() {
+                packets.record(&event);
+                if event.as_ref().is_ok_and(|event| sub_tracker.record(event)) {
+                    ctx.request_repaint();
+                }
                 match event {
                     Ok(Event::Outgoing(Outgoing::Disconnect)) => {
                         log::info!("disconnect happening, exiting!");
                         return;
                     }
+                    Ok(Event::Incoming(Incoming::Publish(message)))
+                        if message.topic == probe.topic() =>
+                    {
+                        probe.record(&message.payload);
+                        ctx.request_repaint();
+                    }
                     Ok(Event::Incoming(Incoming::Publish(message))) => {
+                        if message.topic.starts_with("$SYS/") {
+                            sys.record(&message.topic, &message.payload);
+                        }
                         log::debug!(
                             "published: {} - {}",
                             message.topic,
-                            String::from_utf8(message.payload.to_vec())
-                                .unwrap_or(String::default())
+                            String::from_utf8(message.payload.to_vec()).unwrap_or_default()
                         );
                         if let Err(error) = channel.send(MqttServerManagerEvent {
                             event: message,
@@ -147,7 +468,52 @@ impl Server {
         &self.client
     }
 
-    pub fn sync_subs(&mut self, subs: &Vec<String>) -> Result<(), Box<dyn Error>> {
+    pub fn probe(&self) -> &ProbeState {
+        &self.probe
+    }
+
+    /// Starts or stops publishing timestamps to the private probe topic.
+    pub fn set_probe(&mut self, enabled: bool) {
+        if enabled == self.probe_publisher.is_some() {
+            return;
+        }
+        let topic = self.probe.topic().to_owned();
+        if !enabled {
+            self.probe_publisher = None;
+            self.probe.clear();
+            if let Err(e) = self.send_unsubscribe(topic) {
+                log::error!("Client '{}' cannot stop latency probe: '{}'", self.id, e);
+            }
+            return;
+        }
+        if let Err(e) = self.send_subscribe(topic.clone(), QoS::AtMostOnce) {
+            log::error!("Client '{}' cannot start latency probe: '{}'", self.id, e);
+            return;
+        }
+        let scheduled = ScheduledPublish {
+            id: 0,
+            message: PublishTemplate {
+                topic,
+                payload: String::from("{{now_us}}"),
+                ..PublishTemplate::default()
+            },
+            schedule: Schedule::Every {
+                ms: PROBE_INTERVAL_MS,
+            },
+        };
+        // own sequence, so probing doesn't skip `{{seq}}` values of the user
+        let publisher = Publisher::new(self.id, self.client.clone());
+        self.probe_publisher = RunningSchedule::start(publisher, scheduled).ok();
+    }
+
+    /// Subscribes the enabled, valid filters of `subs` and unsubscribes everything else.
+    pub fn sync_subs(&mut self, subs: &[Subscription]) -> Result<(), Box<dyn Error>> {
+        // invalid filters stored by older versions are kept but not subscribed
+        let subs: Vec<String> = subs
+            .iter()
+            .filter(|sub| sub.enabled && topic::validate_filter(&sub.filter).is_ok())
+            .map(|sub| sub.filter.clone())
+            .collect();
         let to_sub: Vec<String> = subs
             .iter()
             .filter(|sub| !self.current_subs.contains(*sub))
@@ -168,27 +534,180 @@ impl Server {
         Ok(())
     }
 
+    /// Like `sync_subs`, for subscriptions the app makes on its own behalf.
+    pub fn sync_internal_subs(&mut self, subs: &[String]) -> Result<(), Box<dyn Error>> {
+        // filters of bridges, rules and scripts are free text, a malformed one would
+        // get the connection closed by the broker
+        let subs: Vec<String> = subs
+            .iter()
+            .filter(|sub| topic::validate_filter(sub).is_ok())
+            .cloned()
+            .collect();
+        let to_sub: Vec<String> = subs
+            .iter()
+            .filter(|sub| !self.internal_subs.contains(*sub))
+            .cloned()
+            .collect();
+        let to_unsub: Vec<String> = self
+            .internal_subs
+            .iter()
+            .filter(|sub| !subs.contains(sub))
+            .cloned()
+            .collect();
+        for sub in to_sub {
+            // a user subscription of the same filter already delivers it, subscribing
+            // again would replace it with QoS 0
+            if !self.current_subs.contains(&sub) {
+                log::debug!("Client '{}' subscribing internal '{}'", self.id, &sub);
+                self.send_subscribe(sub.clone(), QoS::AtMostOnce)?;
+            }
+            self.internal_subs.insert(sub);
+        }
+        for sub in to_unsub {
+            self.internal_subs.remove(&sub);
+            if !self.current_subs.contains(&sub) {
+                log::debug!("Client '{}' unsubscribing internal '{}'", self.id, &sub);
+                self.send_unsubscribe(sub)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sys(&self) -> &SysState {
+        &self.sys
+    }
+
+    pub fn packets(&self) -> &PacketLog {
+        &self.packets
+    }
+
     fn subscribe(&mut self, topic: String) -> Result<(), Box<dyn Error>> {
+        topic::validate_filter(&topic)?;
         if self.current_subs.insert(topic.clone()) {
             log::debug!("Client '{}' subscribing '{}'", self.id, &topic);
-            if let Err(e) = self.client().subscribe(topic, rumqttc::QoS::ExactlyOnce) {
-                return Err(Box::new(e));
-            };
+            self.send_subscribe(topic, rumqttc::QoS::ExactlyOnce)?;
         }
         Ok(())
     }
 
     fn unsubscribe(&mut self, topic: String) -> Result<(), Box<dyn Error>> {
         if let Some(topic) = self.current_subs.take(&topic) {
+            if self.internal_subs.contains(&topic) {
+                // the broker keeps delivering it for the app
+                self.sub_tracker.set(&topic, SubStatus::Unsubscribed);
+                return Ok(());
+            }
             log::debug!("Client '{}' unsubscribing '{}'", self.id, &topic);
-            if let Err(e) = self.client().unsubscribe(topic) {
-                return Err(Box::new(e));
-            };
+            self.send_unsubscribe(topic)?;
+        }
+        Ok(())
+    }
+
+    /// Subscribes `topic` again, after the broker rejected it for example.
+    pub fn retry_sub(&self, topic: &str) -> Result<(), Box<dyn Error>> {
+        if !self.current_subs.contains(topic) {
+            return Ok(());
         }
+        log::debug!("Client '{}' retrying '{}'", self.id, topic);
+        self.send_subscribe(topic.to_owned(), rumqttc::QoS::ExactlyOnce)?;
         Ok(())
     }
 
-    pub fn publish<S, V>(&self, topic: S, payload: V)
+    pub fn sub_status(&self, topic: &str) -> Option<SubStatus> {
+        self.sub_tracker.status(topic)
+    }
+
+    fn send_subscribe(&self, topic: String, qos: QoS) -> Result<(), ClientError> {
+        self.sub_tracker.subscribing(&topic);
+        self.client.subscribe(topic.clone(), qos).inspect_err(|e| {
+            self.sub_tracker.failed(&topic, true, e.to_string());
+        })
+    }
+
+    fn send_unsubscribe(&self, topic: String) -> Result<(), ClientError> {
+        self.sub_tracker.unsubscribing(&topic);
+        self.client.unsubscribe(topic.clone()).inspect_err(|e| {
+            self.sub_tracker.failed(&topic, false, e.to_string());
+        })
+    }
+
+    pub fn publisher(&self) -> &Publisher {
+        &self.publisher
+    }
+
+    /// Sequence number the next publish renders for `{{seq}}`.
+    pub fn seq(&self) -> u64 {
+        self.publisher.seq()
+    }
+
+    /// Sends `message` as MQTT 5 request and waits for the reply on `response_topic`.
+    pub fn request(
+        &self,
+        message: &PublishTemplate,
+        response_topic: String,
+        timeout: Duration,
+    ) -> PendingRequest {
+        let message = message.render(self.publisher.next_seq());
+        let payload = self.publisher.payload(&message);
+        PendingRequest::start(&self.options, message, payload, response_topic, timeout)
+    }
+
+    pub fn publish<S, V>(&self, topic: S, payload: V, qos: QoS, retain: bool)
+    where
+        S: Into<String> + std::fmt::Debug,
+        V: Into<Vec<u8>> + std::fmt::Debug,
+    {
+        if let Err(e) = self.publisher.publish(topic, payload, qos, retain) {
+            log::error!("Error publishing: {:?}", e);
+        }
+    }
+    pub fn send(&self, message: &PublishTemplate) {
+        if let Err(e) = self.publisher.send(message) {
+            log::error!("Error publishing: {:?}", e);
+        }
+    }
+    #[allow(dead_code)]
+    pub fn join(self) {
+        if self.handle.join().is_err() {
+            log::error!("MQTT Event Loop of client '{}' panicked", self.id);
+        }
+    }
+}
+
+/// Publishing half of a [`Server`], can be cloned into other threads.
+#[derive(Clone)]
+pub struct Publisher {
+    id: u32,
+    client: Client,
+    seq: Arc<AtomicU64>,
+}
+
+impl Publisher {
+    pub fn new(id: u32, client: Client) -> Self {
+        Publisher {
+            id,
+            client,
+            seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq.load(Ordering::Relaxed)
+    }
+
+    /// Takes the value to render for `{{seq}}` in the next message.
+    pub fn next_seq(&self) -> u64 {
+        self.seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Publishes as is, without rendering placeholders.
+    pub fn publish<S, V>(
+        &self,
+        topic: S,
+        payload: V,
+        qos: QoS,
+        retain: bool,
+    ) -> Result<(), ClientError>
     where
         S: Into<String> + std::fmt::Debug,
         V: Into<Vec<u8>> + std::fmt::Debug,
@@ -199,14 +718,36 @@ impl Server {
             &payload,
             &topic
         );
-        if let Err(e) = self
-            .client()
-            .publish(topic, rumqttc::QoS::ExactlyOnce, false, payload)
-        {
-            log::error!("Error publishing: {:?}", e);
-        }
+        self.client.publish(topic, qos, retain, payload)
     }
-    pub fn join(self) {
-        self.handle.join();
+
+    /// Publishes a template with its placeholders rendered, compressing the payload
+    /// if it asks for it.
+    pub fn send(&self, message: &PublishTemplate) -> Result<(), ClientError> {
+        self.send_rendered(&message.render(self.next_seq()))
+    }
+
+    /// Like `send`, for templates whose placeholders are rendered already.
+    pub fn send_rendered(&self, message: &PublishTemplate) -> Result<(), ClientError> {
+        let payload = self.payload(message);
+        self.publish(
+            message.topic.clone(),
+            payload,
+            message.qos(),
+            message.retain,
+        )
+    }
+
+    /// Payload of a rendered template as it goes on the wire.
+    pub fn payload(&self, message: &PublishTemplate) -> Vec<u8> {
+        match message.compression {
+            Compression::None => message.payload.clone().into_bytes(),
+            compression => compression
+                .compress(message.payload.as_bytes())
+                .unwrap_or_else(|e| {
+                    log::error!("Client '{}' sends uncompressed: {}", self.id, e);
+                    message.payload.clone().into_bytes()
+                }),
+        }
     }
 }
@@ -0,0 +1,314 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use egui::{Context, Ui};
+use egui_extras::{Column, TableBuilder};
+use rumqttc::{Event, Incoming, Outgoing, Packet};
+
+use crate::publish::{format_time, now_us};
+
+const MAX_ENTRIES: usize = 10_000;
+
+pub const PACKET_TYPES: [&str; 16] = [
+    "CONNECT",
+    "CONNACK",
+    "PUBLISH",
+    "PUBACK",
+    "PUBREC",
+    "PUBREL",
+    "PUBCOMP",
+    "SUBSCRIBE",
+    "SUBACK",
+    "UNSUBSCRIBE",
+    "UNSUBACK",
+    "PINGREQ",
+    "PINGRESP",
+    "DISCONNECT",
+    "AWAITACK",
+    "ERROR",
+];
+
+#[derive(Clone)]
+pub struct PacketLogEntry {
+    pub at_us: u64,
+    pub incoming: bool,
+    pub kind: &'static str,
+    pub pkid: Option<u16>,
+    pub detail: String,
+}
+
+impl PacketLogEntry {
+    /// Whether the exchange this packet belongs to was started by the client, as
+    /// packet ids of both sides are independent.
+    fn sent_by_client(&self) -> bool {
+        match self.kind {
+            "PUBACK" | "PUBREC" | "PUBCOMP" | "SUBACK" | "UNSUBACK" => self.incoming,
+            "AWAITACK" => true,
+            _ => !self.incoming,
+        }
+    }
+}
+
+/// Every packet going through the event loop of a server.
+pub struct PacketLog {
+    client_id: String,
+    entries: Mutex<VecDeque<PacketLogEntry>>,
+}
+
+impl PacketLog {
+    pub fn new(client_id: String) -> Self {
+        PacketLog {
+            client_id,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, event: &Result<Event, rumqttc::ConnectionError>) {
+        // the event loop doesn't report the CONNECT it sends, only its answer
+        if let Ok(Event::Incoming(Incoming::ConnAck(_))) = event {
+            self.push(false, "CONNECT", None, self.client_id.clone());
+        }
+        let (incoming, kind, pkid, detail) = match event {
+            Ok(Event::Incoming(packet)) => {
+                let (kind, pkid, detail) = describe_incoming(packet);
+                (true, kind, pkid, detail)
+            }
+            Ok(Event::Outgoing(outgoing)) => {
+                let (kind, pkid) = describe_outgoing(outgoing);
+                (false, kind, pkid, String::new())
+            }
+            Err(e) => (true, "ERROR", None, e.to_string()),
+        };
+        self.push(incoming, kind, pkid, detail);
+    }
+
+    fn push(&self, incoming: bool, kind: &'static str, pkid: Option<u16>, detail: String) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push_back(PacketLogEntry {
+                at_us: now_us(),
+                incoming,
+                kind,
+                pkid,
+                detail,
+            });
+            if entries.len() > MAX_ENTRIES {
+                entries.pop_front();
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    pub fn entries(&self) -> Vec<PacketLogEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+fn describe_incoming(packet: &Packet) -> (&'static str, Option<u16>, String) {
+    match packet {
+        Packet::Connect(connect) => ("CONNECT", None, connect.client_id.clone()),
+        Packet::ConnAck(ack) => (
+            "CONNACK",
+            None,
+            format!("{:?}, session present: {}", ack.code, ack.session_present),
+        ),
+        Packet::Publish(publish) => (
+            "PUBLISH",
+            Some(publish.pkid).filter(|pkid| *pkid != 0),
+            format!(
+                "{} ({:?}{}{}, {} bytes)",
+                publish.topic,
+                publish.qos,
+                if publish.retain { ", retain" } else { "" },
+                if publish.dup { ", dup" } else { "" },
+                publish.payload.len()
+            ),
+        ),
+        Packet::PubAck(ack) => ("PUBACK", Some(ack.pkid), String::new()),
+        Packet::PubRec(rec) => ("PUBREC", Some(rec.pkid), String::new()),
+        Packet::PubRel(rel) => ("PUBREL", Some(rel.pkid), String::new()),
+        Packet::PubComp(comp) => ("PUBCOMP", Some(comp.pkid), String::new()),
+        Packet::Subscribe(subscribe) => (
+            "SUBSCRIBE",
+            Some(subscribe.pkid),
+            subscribe
+                .filters
+                .iter()
+                .map(|filter| filter.path.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        Packet::SubAck(ack) => ("SUBACK", Some(ack.pkid), format!("{:?}", ack.return_codes)),
+        Packet::Unsubscribe(unsubscribe) => (
+            "UNSUBSCRIBE",
+            Some(unsubscribe.pkid),
+            unsubscribe.topics.join(", "),
+        ),
+        Packet::UnsubAck(ack) => ("UNSUBACK", Some(ack.pkid), String::new()),
+        Packet::PingReq => ("PINGREQ", None, String::new()),
+        Packet::PingResp => ("PINGRESP", None, String::new()),
+        Packet::Disconnect => ("DISCONNECT", None, String::new()),
+    }
+}
+
+fn describe_outgoing(outgoing: &Outgoing) -> (&'static str, Option<u16>) {
+    match outgoing {
+        Outgoing::Publish(pkid) => ("PUBLISH", Some(*pkid).filter(|pkid| *pkid != 0)),
+        Outgoing::Subscribe(pkid) => ("SUBSCRIBE", Some(*pkid)),
+        Outgoing::Unsubscribe(pkid) => ("UNSUBSCRIBE", Some(*pkid)),
+        Outgoing::PubAck(pkid) => ("PUBACK", Some(*pkid)),
+        Outgoing::PubRec(pkid) => ("PUBREC", Some(*pkid)),
+        Outgoing::PubRel(pkid) => ("PUBREL", Some(*pkid)),
+        Outgoing::PubComp(pkid) => ("PUBCOMP", Some(*pkid)),
+        Outgoing::PingReq => ("PINGREQ", None),
+        Outgoing::PingResp => ("PINGRESP", None),
+        Outgoing::Disconnect => ("DISCONNECT", None),
+        Outgoing::AwaitAck(pkid) => ("AWAITACK", Some(*pkid)),
+    }
+}
+
+/// Filter state of a packet log window.
+#[derive(Default, Clone)]
+pub struct PacketLogFilter {
+    hidden: Vec<&'static str>,
+    /// Packet id followed, with whether the client started the exchange.
+    follow: Option<(u16, bool)>,
+}
+
+pub fn window(
+    ctx: &Context,
+    id: u32,
+    name: &str,
+    open: &mut bool,
+    filter: &mut PacketLogFilter,
+    log: Option<&PacketLog>,
+) {
+    egui::Window::new(format!("Packets: {}", name))
+        .id(format!("packets_{}", id).into())
+        .open(open)
+        .default_width(500.0)
+        .show(ctx, |ui| {
+            let Some(log) = log else {
+                ui.label("not connected");
+                return;
+            };
+            filter_ui(ui, filter);
+            if ui.button("clear").clicked() {
+                log.clear();
+            }
+            ui.separator();
+            let entries: Vec<PacketLogEntry> = log
+                .entries()
+                .into_iter()
+                .rev()
+                .filter(|entry| !filter.hidden.contains(&entry.kind))
+                .filter(|entry| match filter.follow {
+                    Some((pkid, sent)) => {
+                        entry.pkid == Some(pkid) && entry.sent_by_client() == sent
+                    }
+                    None => true,
+                })
+                .collect();
+            TableBuilder::new(ui)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto().at_least(90.0))
+                .column(Column::auto().at_least(40.0))
+                .column(Column::remainder())
+                .striped(true)
+                .header(20.0, |mut header| {
+                    for title in ["Time", "Dir", "Type", "Id", "Detail"] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(16.0, entries.len(), |mut row| {
+                        let entry = &entries[row.index()];
+                        row.col(|ui| {
+                            ui.small(format!(
+                                "{}.{:03}",
+                                format_time(entry.at_us / 1_000_000),
+                                entry.at_us / 1000 % 1000
+                            ));
+                        });
+                        row.col(|ui| {
+                            ui.label(if entry.incoming { "in" } else { "out" });
+                        });
+                        row.col(|ui| {
+                            ui.label(entry.kind);
+                        });
+                        row.col(|ui| {
+                            if let Some(pkid) = entry.pkid {
+                                if ui.link(pkid.to_string()).on_hover_text("follow").clicked() {
+                                    filter.follow = Some((pkid, entry.sent_by_client()));
+                                }
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(&entry.detail);
+                        });
+                    });
+                });
+        });
+}
+
+fn filter_ui(ui: &mut Ui, filter: &mut PacketLogFilter) {
+    ui.horizontal_wrapped(|ui| {
+        for kind in PACKET_TYPES {
+            let mut shown = !filter.hidden.contains(&kind);
+            if ui.checkbox(&mut shown, kind).changed() {
+                filter.hidden.retain(|hidden| *hidden != kind);
+                if !shown {
+                    filter.hidden.push(kind);
+                }
+            }
+        }
+    });
+    if let Some((pkid, sent)) = filter.follow {
+        ui.horizontal(|ui| {
+            let side = if sent { "client" } else { "broker" };
+            ui.label(format!("following packet id {} of the {}", pkid, side));
+            if ui.button("show all").clicked() {
+                filter.follow = None;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::{ConnAck, ConnectReturnCode, PubAck};
+
+    #[test]
+    fn records_connect_before_connack() {
+        let log = PacketLog::new(String::from("client"));
+        log.record(&Ok(Event::Incoming(Incoming::ConnAck(ConnAck::new(
+            ConnectReturnCode::Success,
+            false,
+        )))));
+        let entries = log.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].kind, entries[0].incoming), ("CONNECT", false));
+        assert_eq!(entries[0].detail, "client");
+        assert_eq!((entries[1].kind, entries[1].incoming), ("CONNACK", true));
+    }
+
+    #[test]
+    fn acks_belong_to_the_other_side() {
+        let log = PacketLog::new(String::new());
+        log.record(&Ok(Event::Outgoing(Outgoing::Publish(5))));
+        log.record(&Ok(Event::Incoming(Incoming::PubAck(PubAck::new(5)))));
+        log.record(&Ok(Event::Outgoing(Outgoing::PubAck(5))));
+        let sent: Vec<bool> = log.entries().iter().map(|e| e.sent_by_client()).collect();
+        assert_eq!(sent, [true, true, false]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Placeholders for topics and payloads, rendered on every publish.
+//!
+//! Supported: `{{now}}`, `{{now_ms}}`, `{{now_us}}`, `{{seq}}`, `{{uuid}}`, `{{rand(min,max)}}`,
+//! `{{randf(min,max)}}` and `{{env(NAME)}}`. Unknown placeholders are kept as they are.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn render(template: &str, seq: u64) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let end = start + 2 + len + 2;
+        match evaluate(rest[start + 2..end - 2].trim(), seq) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn evaluate(expression: &str, seq: u64) -> Option<String> {
+    let (name, args): (&str, Vec<&str>) = match expression.split_once('(') {
+        Some((name, args)) => (
+            name.trim(),
+            args.strip_suffix(')')?.split(',').map(str::trim).collect(),
+        ),
+        None => (expression, vec![]),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    match (name, args.as_slice()) {
+        ("now", []) => Some(now.as_secs().to_string()),
+        ("now_ms", []) => Some(now.as_millis().to_string()),
+        ("now_us", []) => Some(now.as_micros().to_string()),
+        ("seq", []) => Some(seq.to_string()),
+        ("uuid", []) => Some(uuid()),
+        ("rand", [min, max]) => {
+            let (min, max): (i64, i64) = (min.parse().ok()?, max.parse().ok()?);
+            (min <= max).then(|| fastrand::i64(min..=max).to_string())
+        }
+        ("randf", [min, max]) => {
+            let (min, max): (f64, f64) = (min.parse().ok()?, max.parse().ok()?);
+            (min <= max).then(|| format!("{:.2}", min + fastrand::f64() * (max - min)))
+        }
+        ("env", [variable]) => std::env::var(variable).ok(),
+        _ => None,
+    }
+}
+
+/// Random version 4 UUID.
+fn uuid() -> String {
+    let bits = fastrand::u128(..) & !(0xf << 76) & !(0x3 << 62) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders() {
+        assert_eq!(render("a/{{seq}}/b", 7), "a/7/b");
+        assert_eq!(render("{{ seq }}{{seq}}", 3), "33");
+        assert_eq!(render("no placeholders", 0), "no placeholders");
+        let uuid = render("{{uuid}}", 0);
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders() {
+        assert_eq!(render("{{nope}} {{seq}}", 1), "{{nope}} 1");
+        assert_eq!(render("{{rand(1)}}", 1), "{{rand(1)}}");
+        assert_eq!(render("{{seq(1)}}", 1), "{{seq(1)}}");
+        assert_eq!(
+            render("{{env(OLDQTT_TEMPLATE_UNSET)}}", 1),
+            "{{env(OLDQTT_TEMPLATE_UNSET)}}"
+        );
+    }
+
+    #[test]
+    fn keeps_unterminated_placeholders() {
+        assert_eq!(render("{{seq}} {{seq", 2), "2 {{seq");
+        assert_eq!(render("{{", 2), "{{");
+        assert_eq!(render("{\"a\": {{seq}}}", 2), "{\"a\": 2}");
+    }
+
+    #[test]
+    fn random_values_stay_in_bounds() {
+        for _ in 0..100 {
+            let value: i64 = render("{{rand(-2, 2)}}", 0).parse().unwrap();
+            assert!((-2..=2).contains(&value));
+            let value: f64 = render("{{randf(1.5, 2.5)}}", 0).parse().unwrap();
+            assert!((1.5..=2.5).contains(&value));
+        }
+        assert_eq!(render("{{rand(3,3)}}", 0), "3");
+        assert_eq!(render("{{rand(3,1)}}", 0), "{{rand(3,1)}}");
+        assert_eq!(render("{{randf(3,1)}}", 0), "{{randf(3,1)}}");
+        assert_eq!(render("{{rand(a,1)}}", 0), "{{rand(a,1)}}");
+    }
+}
@@ -0,0 +1,412 @@
+//! Recognizes binary payloads by their magic bytes and previews them.
+
+use std::{error::Error, fs};
+
+use egui::{ColorImage, Context, TextureHandle, TextureOptions};
+use image::{imageops, imageops::FilterType, RgbaImage};
+
+use crate::message_buffer::ReceivedMessage;
+
+/// Bytes shown in the hex dump of binary payloads.
+const HEX_PREVIEW: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PayloadKind {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Webp,
+    Wav,
+    Mp3,
+    Ogg,
+    Flac,
+    Pdf,
+    Zip,
+    Gzip,
+    Elf,
+    Text,
+    Binary,
+}
+
+impl PayloadKind {
+    pub fn detect(payload: &[u8]) -> Self {
+        let starts = |magic: &[u8]| payload.starts_with(magic);
+        let riff = |format: &[u8]| starts(b"RIFF") && payload.get(8..12) == Some(format);
+        if starts(b"\x89PNG\r\n\x1a\n") {
+            PayloadKind::Png
+        } else if starts(b"\xff\xd8\xff") {
+            PayloadKind::Jpeg
+        } else if starts(b"GIF87a") || starts(b"GIF89a") {
+            PayloadKind::Gif
+        } else if starts(b"BM")
+            && payload.get(2..6) == Some(&(payload.len() as u32).to_le_bytes()[..])
+        {
+            PayloadKind::Bmp
+        } else if riff(b"WEBP") {
+            PayloadKind::Webp
+        } else if riff(b"WAVE") {
+            PayloadKind::Wav
+        } else if (starts(b"ID3") && payload.get(3).is_some_and(|version| *version <= 4))
+            || starts(b"\xff\xfb")
+            || starts(b"\xff\xf3")
+        {
+            PayloadKind::Mp3
+        } else if starts(b"OggS") {
+            PayloadKind::Ogg
+        } else if starts(b"fLaC") {
+            PayloadKind::Flac
+        } else if starts(b"%PDF-") {
+            PayloadKind::Pdf
+        } else if starts(b"PK\x03\x04") {
+            PayloadKind::Zip
+        } else if starts(b"\x1f\x8b") {
+            PayloadKind::Gzip
+        } else if starts(b"\x7fELF") {
+            PayloadKind::Elf
+        } else if std::str::from_utf8(payload).is_ok() {
+            PayloadKind::Text
+        } else {
+            PayloadKind::Binary
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadKind::Png => "PNG image",
+            PayloadKind::Jpeg => "JPEG image",
+            PayloadKind::Gif => "GIF image",
+            PayloadKind::Bmp => "BMP image",
+            PayloadKind::Webp => "WebP image",
+            PayloadKind::Wav => "WAV audio",
+            PayloadKind::Mp3 => "MP3 audio",
+            PayloadKind::Ogg => "Ogg media",
+            PayloadKind::Flac => "FLAC audio",
+            PayloadKind::Pdf => "PDF document",
+            PayloadKind::Zip => "ZIP archive",
+            PayloadKind::Gzip => "gzip data",
+            PayloadKind::Elf => "ELF executable",
+            PayloadKind::Text => "text",
+            PayloadKind::Binary => "binary",
+        }
+    }
+
+    /// Suggested file extension when saving the payload.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PayloadKind::Png => "png",
+            PayloadKind::Jpeg => "jpg",
+            PayloadKind::Gif => "gif",
+            PayloadKind::Bmp => "bmp",
+            PayloadKind::Webp => "webp",
+            PayloadKind::Wav => "wav",
+            PayloadKind::Mp3 => "mp3",
+            PayloadKind::Ogg => "ogg",
+            PayloadKind::Flac => "flac",
+            PayloadKind::Pdf => "pdf",
+            PayloadKind::Zip => "zip",
+            PayloadKind::Gzip => "gz",
+            PayloadKind::Elf => "elf",
+            PayloadKind::Text => "txt",
+            PayloadKind::Binary => "bin",
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self,
+            PayloadKind::Png
+                | PayloadKind::Jpeg
+                | PayloadKind::Gif
+                | PayloadKind::Bmp
+                | PayloadKind::Webp
+        )
+    }
+}
+
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1048576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// Short description for the message table, `None` for text payloads.
+pub fn summary(payload: &[u8]) -> Option<String> {
+    match PayloadKind::detect(payload) {
+        PayloadKind::Text => None,
+        kind => Some(format!("[{}, {}]", kind.name(), format_size(payload.len()))),
+    }
+}
+
+/// Channels, sample rate and duration from the `fmt ` and `data` chunks of a WAV file.
+pub fn wav_info(payload: &[u8]) -> Option<String> {
+    let u16_at = |at: usize| {
+        Some(u16::from_le_bytes(
+            payload.get(at..at + 2)?.try_into().ok()?,
+        ))
+    };
+    let u32_at = |at: usize| {
+        Some(u32::from_le_bytes(
+            payload.get(at..at + 4)?.try_into().ok()?,
+        ))
+    };
+    let mut format = None;
+    let mut data_len = None;
+    let mut at = 12;
+    while at + 8 <= payload.len() {
+        let id = &payload[at..at + 4];
+        let len = u32_at(at + 4)? as usize;
+        match id {
+            b"fmt " => format = Some((u16_at(at + 10)?, u32_at(at + 12)?, u32_at(at + 16)?)),
+            b"data" => data_len = Some(len),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        at += 8 + len + len % 2;
+    }
+    let (channels, sample_rate, byte_rate) = format?;
+    let mut info = format!("{} channels, {} Hz", channels, sample_rate);
+    if let (Some(data_len), true) = (data_len, byte_rate > 0) {
+        info += &format!(", {:.1} s", data_len as f64 / byte_rate as f64);
+    }
+    Some(info)
+}
+
+pub fn hex_dump(payload: &[u8]) -> String {
+    payload
+        .chunks(16)
+        .take(HEX_PREVIEW / 16)
+        .enumerate()
+        .map(|(i, row)| {
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = row
+                .iter()
+                .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                    true => *b as char,
+                    false => '.',
+                })
+                .collect();
+            format!("{:08x}  {:<47}  {}", i * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Decodes an image, scaled down to fit into textures of `max_side` pixels.
+fn decode_image(payload: &[u8], max_side: usize) -> Result<ColorImage, Box<dyn Error>> {
+    let image = fit(image::load_from_memory(payload)?.to_rgba8(), max_side);
+    let size = [image.width() as usize, image.height() as usize];
+    Ok(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+}
+
+fn fit(image: RgbaImage, max_side: usize) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let longest = width.max(height) as usize;
+    if longest <= max_side {
+        return image;
+    }
+    let scale = max_side as f64 / longest as f64;
+    let scaled = |side: u32| ((side as f64 * scale) as u32).clamp(1, max_side as u32);
+    imageops::resize(&image, scaled(width), scaled(height), FilterType::Triangle)
+}
+
+/// Details of one message, with a preview of recognized formats.
+#[derive(Clone, Default)]
+pub struct DetailView {
+    pub open: bool,
+    message: Option<ReceivedMessage>,
+    texture: Option<TextureHandle>,
+    image_error: String,
+    path: String,
+    status: String,
+}
+
+impl DetailView {
+    pub fn show(&mut self, message: &ReceivedMessage) {
+        let kind = PayloadKind::detect(&message.event.payload);
+        self.path = format!("payload_{}.{}", message.seq, kind.extension());
+        self.message = Some(message.clone());
+        self.texture = None;
+        self.image_error.clear();
+        self.status.clear();
+        self.open = true;
+    }
+
+    pub fn window(&mut self, ctx: &Context, id: u32, name: &str) {
+        let mut open = self.open;
+        egui::Window::new(format!("Message: {}", name))
+            .id(format!("detail_{}", id).into())
+            .open(&mut open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                let Some(message) = &self.message else {
+                    return;
+                };
+                let event = &message.event;
+                let payload = &event.payload[..];
+                let kind = PayloadKind::detect(payload);
+                egui::Grid::new("message_detail").show(ui, |ui| {
+                    ui.label("topic");
+                    ui.label(event.topic.as_str());
+                    ui.end_row();
+                    ui.label("QoS");
+                    ui.label(format!("{}", event.qos as u8));
+                    ui.end_row();
+                    ui.label("retain");
+                    ui.label(event.retain.to_string());
+                    ui.end_row();
+                    ui.label("size");
+                    ui.label(format_size(payload.len()));
+                    ui.end_row();
+                    ui.label("type");
+                    ui.label(kind.name());
+                    ui.end_row();
+                    if let Some((compression, size)) = message.compressed {
+                        ui.label("compression");
+                        ui.label(format!(
+                            "{}, {} decompressed from {}",
+                            compression.name(),
+                            format_size(payload.len()),
+                            format_size(size)
+                        ));
+                        ui.end_row();
+                    }
+                    if kind == PayloadKind::Wav {
+                        ui.label("audio");
+                        ui.label(wav_info(payload).unwrap_or(String::from("unreadable header")));
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.path);
+                    if ui.button("save payload as").clicked() {
+                        self.status = match fs::write(&self.path, payload) {
+                            Ok(()) => format!("saved {}", format_size(payload.len())),
+                            Err(e) => format!("save failed: {}", e),
+                        };
+                    }
+                });
+                if !self.status.is_empty() {
+                    ui.small(&self.status);
+                }
+                ui.separator();
+                egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                    if kind.is_image() {
+                        if self.texture.is_none() && self.image_error.is_empty() {
+                            let max_side = ctx.input(|i| i.max_texture_side);
+                            match decode_image(payload, max_side) {
+                                Ok(image) => {
+                                    self.texture = Some(ctx.load_texture(
+                                        format!("payload_{}_{}", id, message.seq),
+                                        image,
+                                        TextureOptions::default(),
+                                    ))
+                                }
+                                Err(e) => self.image_error = e.to_string(),
+                            }
+                        }
+                        if let Some(texture) = &self.texture {
+                            let size = texture.size_vec2();
+                            ui.small(format!("{} x {} px", size.x, size.y));
+                            ui.image((texture.id(), size));
+                        } else {
+                            ui.colored_label(ui.visuals().error_fg_color, &self.image_error);
+                        }
+                    } else if kind == PayloadKind::Text {
+                        ui.label(String::from_utf8_lossy(payload));
+                    } else {
+                        if payload.len() > HEX_PREVIEW {
+                            ui.small(format!("first {} bytes", HEX_PREVIEW));
+                        }
+                        ui.monospace(hex_dump(payload));
+                    }
+                });
+            });
+        self.open = open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal WAV file with a `fmt ` chunk and `data_len` bytes of silence.
+    fn wav(channels: u16, sample_rate: u32, data_len: u32) -> Vec<u8> {
+        let byte_rate = sample_rate * channels as u32 * 2;
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&byte_rate.to_le_bytes());
+        wav.extend_from_slice(&(channels * 2).to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.resize(wav.len() + data_len as usize, 0);
+        wav
+    }
+
+    #[test]
+    fn detects_magic_bytes() {
+        let cases: [(&[u8], PayloadKind); 9] = [
+            (b"\x89PNG\r\n\x1a\n\0\0", PayloadKind::Png),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", PayloadKind::Jpeg),
+            (b"GIF89a\x01\0", PayloadKind::Gif),
+            (b"RIFF\0\0\0\0WEBPVP8 ", PayloadKind::Webp),
+            (b"ID3\x04\0\0\0\0\0\0", PayloadKind::Mp3),
+            (b"%PDF-1.7\n", PayloadKind::Pdf),
+            (b"\x1f\x8b\x08\0", PayloadKind::Gzip),
+            (b"{\"on\": true}", PayloadKind::Text),
+            (b"\x00\xfe\xff\x01", PayloadKind::Binary),
+        ];
+        for (payload, kind) in cases {
+            assert!(PayloadKind::detect(payload) == kind, "{:?}", payload);
+        }
+        assert!(PayloadKind::detect(&wav(1, 8000, 4)) == PayloadKind::Wav);
+    }
+
+    #[test]
+    fn text_resembling_magic_bytes_stays_text() {
+        assert!(PayloadKind::detect(b"BMW engine temperature 90") == PayloadKind::Text);
+        assert!(PayloadKind::detect(b"ID3 tag") == PayloadKind::Text);
+        let mut bmp = b"BM".to_vec();
+        bmp.extend_from_slice(&16u32.to_le_bytes());
+        bmp.resize(16, 0);
+        assert!(PayloadKind::detect(&bmp) == PayloadKind::Bmp);
+    }
+
+    #[test]
+    fn reads_wav_header() {
+        assert_eq!(
+            wav_info(&wav(2, 44100, 176400)).as_deref(),
+            Some("2 channels, 44100 Hz, 1.0 s")
+        );
+        assert_eq!(wav_info(b"RIFF\0\0\0\0WAVE"), None);
+        // a chunk length pointing past the end must not panic
+        let mut truncated = wav(1, 8000, 0);
+        truncated.truncate(30);
+        assert_eq!(wav_info(&truncated), None);
+    }
+
+    #[test]
+    fn dumps_hex_with_ascii() {
+        assert_eq!(
+            hex_dump(b"ab\x00"),
+            format!("00000000  {:<47}  ab.", "61 62 00")
+        );
+        let dump = hex_dump(&[0u8; HEX_PREVIEW * 2]);
+        assert_eq!(dump.lines().count(), HEX_PREVIEW / 16);
+        assert!(dump.lines().nth(1).unwrap().starts_with("00000010"));
+    }
+
+    #[test]
+    fn large_images_fit_into_textures() {
+        let image = fit(RgbaImage::new(4000, 1000), 2048);
+        assert_eq!(image.dimensions(), (2048, 512));
+        let image = fit(RgbaImage::new(10, 20), 2048);
+        assert_eq!(image.dimensions(), (10, 20));
+    }
+}
@@ -0,0 +1,112 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use egui::{Color32, Sense, Stroke, Ui, Vec2};
+use egui_plot::{Line, Plot, PlotPoints};
+
+use crate::publish::now_us;
+
+/// Samples kept for percentiles and the history chart.
+const MAX_SAMPLES: usize = 300;
+
+/// Round trip measurements of the latency probe of a single server.
+pub struct ProbeState {
+    topic: String,
+    samples: Mutex<VecDeque<f64>>,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct ProbeStats {
+    pub current: f64,
+    pub p50: f64,
+    pub p99: f64,
+}
+
+impl ProbeState {
+    pub fn new(id: u32) -> Self {
+        ProbeState {
+            topic: format!("oldqtt/probe/{:x}/{:x}", id, fastrand::u32(..)),
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Records a probe message carrying its send time in microseconds.
+    pub fn record(&self, payload: &[u8]) {
+        let Some(sent_us) = std::str::from_utf8(payload)
+            .ok()
+            .and_then(|payload| payload.parse::<u64>().ok())
+        else {
+            log::warn!("Malformed probe message on '{}'", self.topic);
+            return;
+        };
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.push_back(now_us().saturating_sub(sent_us) as f64 / 1000.0);
+            if samples.len() > MAX_SAMPLES {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.clear();
+        }
+    }
+
+    /// Latencies in milliseconds, oldest first.
+    pub fn history(&self) -> Vec<f64> {
+        self.samples
+            .lock()
+            .map(|samples| samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn stats(&self) -> Option<ProbeStats> {
+        let mut history = self.history();
+        let current = *history.last()?;
+        history.sort_by(f64::total_cmp);
+        let percentile = |p: f64| history[((history.len() - 1) as f64 * p).round() as usize];
+        Some(ProbeStats {
+            current,
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+        })
+    }
+}
+
+/// Tiny inline chart for the server list.
+pub fn sparkline(ui: &mut Ui, history: &[f64]) {
+    let (response, painter) = ui.allocate_painter(Vec2::new(60.0, 16.0), Sense::hover());
+    let rect = response.rect;
+    let max = history.iter().copied().fold(f64::EPSILON, f64::max);
+    let step = rect.width() / (history.len().max(2) - 1) as f32;
+    let points = history
+        .iter()
+        .enumerate()
+        .map(|(i, ms)| {
+            egui::pos2(
+                rect.left() + i as f32 * step,
+                rect.bottom() - (ms / max) as f32 * rect.height(),
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        Stroke::new(1.0, Color32::LIGHT_BLUE),
+    ));
+}
+
+pub fn plot(ui: &mut Ui, id: u32, history: &[f64]) {
+    Plot::new(format!("probe_{}", id))
+        .height(80.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .y_axis_label("ms")
+        .show(ui, |plot| {
+            plot.line(Line::new(PlotPoints::from_ys_f64(history)).name("latency"));
+        });
+}
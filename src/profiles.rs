@@ -0,0 +1,202 @@
+use std::{collections::HashSet, error::Error, fs, path::Path};
+
+use egui::{ahash::HashMap, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::app::MqttServer;
+
+const PROFILE_VERSION: u32 = 1;
+
+/// File format used to share server profiles between machines.
+#[derive(Serialize, Deserialize)]
+struct ProfileFile {
+    version: u32,
+    servers: Vec<ProfileEntry>,
+}
+
+/// Passwords are not part of the persisted server, so they travel next to it.
+#[derive(Serialize, Deserialize)]
+struct ProfileEntry {
+    #[serde(flatten)]
+    server: MqttServer,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+}
+
+pub fn export<P>(
+    path: P,
+    servers: &[&MqttServer],
+    include_secrets: bool,
+) -> Result<(), Box<dyn Error>>
+where
+    P: AsRef<Path>,
+{
+    let file = ProfileFile {
+        version: PROFILE_VERSION,
+        servers: servers
+            .iter()
+            .map(|server| ProfileEntry {
+                server: server.to_profile(include_secrets),
+                password: (include_secrets && !server.password().is_empty())
+                    .then(|| server.password().to_owned()),
+            })
+            .collect(),
+    };
+    fs::write(path, serde_json::to_string_pretty(&file)?)?;
+    Ok(())
+}
+
+pub fn import<P>(path: P) -> Result<Vec<MqttServer>, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+{
+    let file: ProfileFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if !(1..=PROFILE_VERSION).contains(&file.version) {
+        return Err(format!("unsupported profile version '{}'", file.version).into());
+    }
+    Ok(file
+        .servers
+        .into_iter()
+        .map(|entry| {
+            let mut server = entry.server.to_profile(true);
+            if let Some(password) = entry.password {
+                server.set_password(password);
+            }
+            server
+        })
+        .collect())
+}
+
+#[derive(Default)]
+pub struct ProfilesWindow {
+    pub open: bool,
+    path: String,
+    selected: HashSet<u32>,
+    include_secrets: bool,
+    status: String,
+}
+
+impl ProfilesWindow {
+    /// Shows the import/export window, returns the imported servers.
+    pub fn show(&mut self, ctx: &Context, servers: &HashMap<u32, MqttServer>) -> Vec<MqttServer> {
+        let mut imported = vec![];
+        egui::Window::new("Import / Export Profiles")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.path)
+                            .hint_text("oldqtt_profiles.json"),
+                    );
+                    ui.label("File");
+                });
+                ui.separator();
+                ui.heading("Export");
+                for (id, server) in servers.iter() {
+                    let mut selected = self.selected.contains(id);
+                    if ui.checkbox(&mut selected, server.name()).changed() {
+                        if selected {
+                            self.selected.insert(*id);
+                        } else {
+                            self.selected.remove(id);
+                        }
+                    }
+                }
+                ui.checkbox(&mut self.include_secrets, "include passwords");
+                if ui.button("Export").clicked() {
+                    let export_servers: Vec<&MqttServer> = servers
+                        .iter()
+                        .filter(|(id, _)| self.selected.contains(id))
+                        .map(|(_, server)| server)
+                        .collect();
+                    self.status = match export(&self.path, &export_servers, self.include_secrets) {
+                        Ok(()) => format!("exported {} profiles", export_servers.len()),
+                        Err(e) => format!("export failed: {}", e),
+                    };
+                }
+                ui.separator();
+                ui.heading("Import");
+                if ui.button("Import").clicked() {
+                    match import(&self.path) {
+                        Ok(servers) => {
+                            self.status = format!("imported {} profiles", servers.len());
+                            imported = servers;
+                        }
+                        Err(e) => self.status = format!("import failed: {}", e),
+                    }
+                }
+                if !self.status.is_empty() {
+                    ui.separator();
+                    ui.small(&self.status);
+                }
+            });
+        imported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> MqttServer {
+        let mut server: MqttServer = serde_json::from_value(serde_json::json!({
+            "name": "broker",
+            "host": "mqtt.example.com",
+            "port": "8883",
+            "username": "user",
+        }))
+        .unwrap();
+        server.set_password(String::from("hunter2"));
+        server
+    }
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("oldqtt_{}_{:x}.json", name, fastrand::u64(..)))
+    }
+
+    #[test]
+    fn round_trip_with_secrets() {
+        let path = path("profile_secrets");
+        export(&path, &[&server()], true).unwrap();
+        let servers = import(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name(), "broker");
+        assert_eq!(servers[0].host(), "mqtt.example.com");
+        assert_eq!(servers[0].port(), 8883);
+        assert_eq!(
+            servers[0].credentials(),
+            Some((String::from("user"), String::from("hunter2")))
+        );
+    }
+
+    #[test]
+    fn round_trip_without_secrets() {
+        let path = path("profile_plain");
+        export(&path, &[&server()], false).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let servers = import(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(!text.contains("hunter2"));
+        assert_eq!(
+            servers[0].credentials(),
+            Some((String::from("user"), String::new()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let path = path("profile_version");
+        for version in [0, PROFILE_VERSION + 1] {
+            fs::write(
+                &path,
+                format!(r#"{{"version": {}, "servers": []}}"#, version),
+            )
+            .unwrap();
+            assert!(import(&path).is_err(), "{}", version);
+        }
+        fs::write(&path, r#"{"servers": []}"#).unwrap();
+        assert!(import(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compression::{compression_combo, Compression},
+    mqtt_servermanager::Server,
+    payload_template,
+    request::{PendingRequest, RequestState},
+    topic,
+};
+
+const MAX_HISTORY: usize = 200;
+const MAX_REQUESTS: usize = 20;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 5;
+
+/// A message to publish, either saved under a name or sent from the form.
+#[derive(Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PublishTemplate {
+    pub name: String,
+    pub topic: String,
+    pub payload: String,
+    pub qos: u8,
+    pub retain: bool,
+    /// Applied to the payload after rendering its placeholders.
+    pub compression: Compression,
+}
+
+impl PublishTemplate {
+    pub fn qos(&self) -> rumqttc::QoS {
+        rumqttc::qos(self.qos).unwrap_or(rumqttc::QoS::AtMostOnce)
+    }
+
+    /// Copy with the placeholders of topic and payload rendered.
+    pub fn render(&self, seq: u64) -> PublishTemplate {
+        PublishTemplate {
+            topic: payload_template::render(&self.topic, seq),
+            payload: payload_template::render(&self.payload, seq),
+            ..self.clone()
+        }
+    }
+
+    /// Checks the topic with its placeholders rendered.
+    pub fn validate_topic(&self) -> Result<(), String> {
+        topic::validate_topic(&payload_template::render(&self.topic, 0))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PublishRecord {
+    pub message: PublishTemplate,
+    /// Seconds since the unix epoch.
+    pub sent_at: u64,
+}
+
+/// Publish form, saved templates and history of a single server.
+#[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PublishPanel {
+    form: PublishTemplate,
+    templates: Vec<PublishTemplate>,
+    history: VecDeque<PublishRecord>,
+    #[serde(skip)]
+    history_filter: String,
+    #[serde(skip)]
+    preview: String,
+    /// Empty picks a private topic per request.
+    response_topic: String,
+    request_timeout_secs: u64,
+    #[serde(skip)]
+    requests: VecDeque<PendingRequest>,
+}
+
+impl PublishPanel {
+    pub fn form(&self) -> &PublishTemplate {
+        &self.form
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    pub fn send(&mut self, server: &Server, message: PublishTemplate) {
+        server.send(&message);
+        self.history.push_front(PublishRecord {
+            message,
+            sent_at: now(),
+        });
+        self.history.truncate(MAX_HISTORY);
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, server: Option<&Server>) {
+        let connected = server.is_some();
+        let valid = self.form.validate_topic();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.form.topic)
+                    .hint_text("topic")
+                    .interactive(connected),
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut self.form.payload)
+                    .hint_text("payload")
+                    .interactive(connected),
+            );
+            qos_combo(ui, "pub_qos", &mut self.form.qos);
+            ui.checkbox(&mut self.form.retain, "retain");
+            compression_combo(ui, "pub_compression", &mut self.form.compression);
+            if ui
+                .add_enabled(valid.is_ok(), egui::Button::new("publish"))
+                .clicked()
+            {
+                if let Some(server) = server {
+                    self.send(server, self.form.clone());
+                }
+            };
+            if ui.button("preview").clicked() {
+                let seq = server.map(Server::seq).unwrap_or_default();
+                self.preview = format!(
+                    "{}: {}",
+                    payload_template::render(&self.form.topic, seq),
+                    payload_template::render(&self.form.payload, seq)
+                );
+            }
+        });
+        if let Err(e) = &valid {
+            if !self.form.topic.is_empty() {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+        }
+        if !self.preview.is_empty() {
+            ui.horizontal(|ui| {
+                ui.small(&self.preview);
+                if ui.small_button("x").clicked() {
+                    self.preview.clear();
+                }
+            });
+        }
+        self.request_ui(ui, server);
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.form.name).hint_text("template name"));
+            if ui.button("save template").clicked() && !self.form.name.is_empty() {
+                self.templates.retain(|t| t.name != self.form.name);
+                self.templates.push(self.form.clone());
+            }
+        });
+        let mut send = None;
+        ui.collapsing(format!("Templates ({})", self.templates.len()), |ui| {
+            let mut delete = None;
+            for (i, template) in self.templates.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let valid = template.validate_topic();
+                    if ui
+                        .add_enabled(connected && valid.is_ok(), egui::Button::new("send"))
+                        .on_disabled_hover_text(valid.err().unwrap_or_default())
+                        .clicked()
+                    {
+                        send = Some(template.clone());
+                    }
+                    if ui.button("edit").clicked() {
+                        self.form = template.clone();
+                    }
+                    if ui.button("del").clicked() {
+                        delete = Some(i);
+                    }
+                    ui.label(&template.name);
+                    ui.small(format!("{} (QoS {})", template.topic, template.qos));
+                });
+            }
+            if let Some(i) = delete {
+                self.templates.remove(i);
+            }
+        });
+        ui.collapsing(format!("History ({})", self.history.len()), |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.history_filter).hint_text("search"));
+                if ui.button("clear").clicked() {
+                    self.history.clear();
+                }
+            });
+            egui::ScrollArea::vertical()
+                .id_salt("publish_history")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for record in self.history.iter().filter(|r| {
+                        r.message.topic.contains(&self.history_filter)
+                            || r.message.payload.contains(&self.history_filter)
+                    }) {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(connected, egui::Button::new("resend"))
+                                .clicked()
+                            {
+                                send = Some(record.message.clone());
+                            }
+                            if ui.button("edit").clicked() {
+                                self.form = record.message.clone();
+                            }
+                            ui.small(format_time(record.sent_at));
+                            ui.label(&record.message.topic);
+                            ui.small(&record.message.payload);
+                        });
+                    }
+                });
+        });
+        if let (Some(server), Some(message)) = (server, send) {
+            self.send(server, message);
+        }
+    }
+
+    /// MQTT 5 request/response with the form as request.
+    fn request_ui(&mut self, ui: &mut Ui, server: Option<&Server>) {
+        if self.request_timeout_secs == 0 {
+            self.request_timeout_secs = DEFAULT_REQUEST_TIMEOUT_SECS;
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.response_topic)
+                    .hint_text("response topic (private if empty)"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.request_timeout_secs)
+                    .range(1..=300)
+                    .suffix(" s"),
+            );
+            let valid =
+                self.form
+                    .validate_topic()
+                    .and_then(|()| match self.response_topic.is_empty() {
+                        true => Ok(()),
+                        false => topic::validate_topic(&self.response_topic),
+                    });
+            if ui
+                .add_enabled(
+                    server.is_some() && valid.is_ok(),
+                    egui::Button::new("request"),
+                )
+                .clicked()
+            {
+                if let Some(server) = server {
+                    let response_topic = match self.response_topic.is_empty() {
+                        true => format!("oldqtt/response/{:016x}", fastrand::u64(..)),
+                        false => self.response_topic.clone(),
+                    };
+                    let timeout = Duration::from_secs(self.request_timeout_secs);
+                    let request = server.request(&self.form, response_topic, timeout);
+                    self.requests.push_front(request);
+                    self.requests.truncate(MAX_REQUESTS);
+                }
+            }
+        });
+        if self.requests.is_empty() {
+            return;
+        }
+        ui.collapsing(format!("Requests ({})", self.requests.len()), |ui| {
+            if ui.button("clear").clicked() {
+                self.requests.clear();
+            }
+            egui::Grid::new("requests").striped(true).show(ui, |ui| {
+                for request in &self.requests {
+                    ui.small(format_time(request.sent_at));
+                    ui.vertical(|ui| {
+                        ui.label(format!(
+                            "{}: {}",
+                            request.message.topic, request.message.payload
+                        ));
+                        ui.small(format!(
+                            "reply to {}, correlation {}",
+                            request.response_topic, request.correlation
+                        ));
+                    });
+                    match request.state() {
+                        RequestState::Waiting => {
+                            ui.spinner();
+                            ui.ctx().request_repaint_after(Duration::from_millis(100));
+                        }
+                        RequestState::Replied {
+                            topic,
+                            payload,
+                            after_ms,
+                        } => {
+                            ui.vertical(|ui| {
+                                ui.label(payload);
+                                ui.small(format!("{} after {} ms", topic, after_ms));
+                            });
+                        }
+                        RequestState::TimedOut => {
+                            ui.colored_label(ui.visuals().warn_fg_color, "timed out");
+                        }
+                        RequestState::Failed(e) => {
+                            ui.colored_label(ui.visuals().error_fg_color, e);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+pub fn qos_combo(ui: &mut Ui, id: &str, qos: &mut u8) {
+    egui::ComboBox::from_id_salt(id)
+        .width(60.0)
+        .selected_text(format!("QoS {}", qos))
+        .show_ui(ui, |ui| {
+            for level in 0..=2 {
+                ui.selectable_value(qos, level, format!("QoS {}", level));
+            }
+        });
+}
+
+/// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Microseconds since the unix epoch.
+pub fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+/// Formats seconds since the unix epoch as UTC time of day.
+pub fn format_time(secs: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
@@ -0,0 +1,157 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rumqttc::{
+    v5::{
+        mqttbytes::{v5::PublishProperties, v5::SubscribeReasonCode, QoS},
+        Client, Event, Incoming, MqttOptions,
+    },
+    Outgoing,
+};
+
+use crate::publish::{now, PublishTemplate};
+
+#[derive(Clone)]
+pub enum RequestState {
+    Waiting,
+    Replied {
+        topic: String,
+        payload: String,
+        after_ms: u128,
+    },
+    TimedOut,
+    Failed(String),
+}
+
+/// A request sent with response topic and correlation data, and its reply.
+#[derive(Clone)]
+pub struct PendingRequest {
+    pub message: PublishTemplate,
+    pub response_topic: String,
+    pub correlation: String,
+    /// Seconds since the unix epoch.
+    pub sent_at: u64,
+    state: Arc<Mutex<RequestState>>,
+}
+
+impl PendingRequest {
+    /// Sends `message` on a short lived MQTT 5 connection to the broker of `options`, the
+    /// regular connection speaks MQTT 3.1.1 which has no response topic or correlation data.
+    /// `payload` is the payload of `message` as it goes on the wire.
+    pub fn start(
+        options: &rumqttc::MqttOptions,
+        message: PublishTemplate,
+        payload: Vec<u8>,
+        response_topic: String,
+        timeout: Duration,
+    ) -> Self {
+        let correlation = format!("{:016x}", fastrand::u64(..));
+        let (host, port) = options.broker_address();
+        let mut v5_options = MqttOptions::new(
+            format!("{}_request_{}", options.client_id(), &correlation[..8]),
+            host,
+            port,
+        );
+        v5_options.set_max_packet_size(Some(6000000));
+        if let Some((username, password)) = options.credentials() {
+            v5_options.set_credentials(username, password);
+        }
+        let state = Arc::new(Mutex::new(RequestState::Waiting));
+        let request = PendingRequest {
+            message,
+            response_topic,
+            correlation,
+            sent_at: now(),
+            state: state.clone(),
+        };
+        let (message, response_topic, correlation) = (
+            request.message.clone(),
+            request.response_topic.clone(),
+            request.correlation.clone(),
+        );
+        std::thread::spawn(move || {
+            let set = |new: RequestState| {
+                if let Ok(mut state) = state.lock() {
+                    *state = new;
+                }
+            };
+            let (client, mut connection) = Client::new(v5_options, 10);
+            if let Err(e) = client.subscribe(&response_topic, QoS::AtLeastOnce) {
+                set(RequestState::Failed(e.to_string()));
+                return;
+            }
+            let started = Instant::now();
+            let mut sent = None;
+            let result = loop {
+                if started.elapsed() >= timeout {
+                    break RequestState::TimedOut;
+                }
+                match connection.recv_timeout(Duration::from_millis(100)) {
+                    Ok(Ok(Event::Incoming(Incoming::SubAck(ack)))) => {
+                        if !matches!(ack.return_codes[..], [SubscribeReasonCode::Success(_)]) {
+                            break RequestState::Failed(format!(
+                                "cannot subscribe to response topic: {:?}",
+                                ack.return_codes
+                            ));
+                        }
+                        let properties = PublishProperties {
+                            response_topic: Some(response_topic.clone()),
+                            correlation_data: Some(correlation.clone().into()),
+                            ..PublishProperties::default()
+                        };
+                        let qos =
+                            rumqttc::v5::mqttbytes::qos(message.qos).unwrap_or(QoS::AtMostOnce);
+                        if let Err(e) = client.publish_with_properties(
+                            message.topic.clone(),
+                            qos,
+                            message.retain,
+                            payload.clone(),
+                            properties,
+                        ) {
+                            break RequestState::Failed(e.to_string());
+                        }
+                        sent = Some(Instant::now());
+                    }
+                    Ok(Ok(Event::Incoming(Incoming::Publish(reply)))) => {
+                        let reply_topic = String::from_utf8_lossy(&reply.topic).into_owned();
+                        let matching = reply
+                            .properties
+                            .as_ref()
+                            .and_then(|p| p.correlation_data.as_ref())
+                            .is_some_and(|data| data.as_ref() == correlation.as_bytes());
+                        if matching && reply_topic == response_topic {
+                            break RequestState::Replied {
+                                topic: reply_topic,
+                                payload: String::from_utf8_lossy(&reply.payload).into_owned(),
+                                after_ms: sent.unwrap_or(started).elapsed().as_millis(),
+                            };
+                        }
+                    }
+                    Ok(Err(e)) => break RequestState::Failed(e.to_string()),
+                    _ => {}
+                }
+            };
+            set(result);
+            if client.disconnect().is_ok() {
+                // the disconnect only goes out while the connection is polled
+                let deadline = Instant::now() + Duration::from_secs(1);
+                while Instant::now() < deadline {
+                    match connection.recv_timeout(Duration::from_millis(100)) {
+                        Ok(Ok(Event::Outgoing(Outgoing::Disconnect))) | Ok(Err(_)) => break,
+                        _ => {}
+                    }
+                }
+            }
+        });
+        request
+    }
+
+    pub fn state(&self) -> RequestState {
+        self.state
+            .lock()
+            .map(|state| state.clone())
+            .unwrap_or(RequestState::Waiting)
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use egui::Ui;
+use egui_extras::{Column, TableBuilder};
+use rumqttc::{Client, Event, Incoming, QoS};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::MqttServer,
+    mqtt_servermanager::{mqtt_options, MqttServerManager},
+    publish::now,
+};
+
+/// The scan is done once no retained message arrived for this long after the SUBACK.
+const QUIET: Duration = Duration::from_secs(2);
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+const PREVIEW_LEN: usize = 80;
+
+#[derive(Clone)]
+pub struct RetainedMessage {
+    pub topic: String,
+    pub size: usize,
+    pub preview: String,
+    /// Age derived from a timestamp field in the payload, if there is one.
+    pub age_secs: Option<u64>,
+}
+
+/// Collects the retained messages below a filter with a short lived client of its own.
+pub struct RetainedScan {
+    filter: String,
+    messages: Arc<Mutex<Vec<RetainedMessage>>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RetainedScan {
+    pub fn start(id: u32, server: &MqttServer, filter: String) -> Self {
+        let messages: Arc<Mutex<Vec<RetainedMessage>>> = Arc::new(Mutex::new(vec![]));
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = mqtt_options(server, format!("oldqtt_{:x}_retained", id));
+        let (client, mut connection) = Client::new(options, 100);
+        if let Err(e) = client.subscribe(&filter, QoS::AtMostOnce) {
+            log::error!("Retained scan cannot subscribe to '{}': {}", filter, e);
+        }
+        let (messages_c, stop_c) = (messages.clone(), stop.clone());
+        let handle = std::thread::spawn(move || {
+            let started = Instant::now();
+            let mut last: Option<Instant> = None;
+            while !stop_c.load(Ordering::Relaxed)
+                && started.elapsed() < SCAN_TIMEOUT
+                && !last.is_some_and(|last| last.elapsed() >= QUIET)
+            {
+                match connection.recv_timeout(Duration::from_millis(100)) {
+                    Ok(Ok(Event::Incoming(Incoming::SubAck(_)))) => last = Some(Instant::now()),
+                    Ok(Ok(Event::Incoming(Incoming::Publish(message)))) if message.retain => {
+                        last = Some(Instant::now());
+                        let message = RetainedMessage {
+                            size: message.payload.len(),
+                            preview: preview(&message.payload),
+                            age_secs: age_hint(&message.payload),
+                            topic: message.topic,
+                        };
+                        if let Ok(mut messages) = messages_c.lock() {
+                            messages.retain(|m| m.topic != message.topic);
+                            messages.push(message);
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        log::error!("Retained scan error: {:?}", e);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let _ = client.disconnect();
+        });
+        RetainedScan {
+            filter,
+            messages,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    pub fn messages(&self) -> Vec<RetainedMessage> {
+        let mut messages = self
+            .messages
+            .lock()
+            .map(|messages| messages.clone())
+            .unwrap_or_default();
+        messages.sort_by(|a, b| a.topic.cmp(&b.topic));
+        messages
+    }
+
+    /// Drops cleared topics from the results.
+    pub fn forget(&self, topics: &BTreeSet<String>) {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.retain(|message| !topics.contains(&message.topic));
+        }
+    }
+}
+
+impl Drop for RetainedScan {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn preview(payload: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&payload[..payload.len().min(PREVIEW_LEN)]);
+    text.chars()
+        .map(|c| if c.is_control() { '.' } else { c })
+        .collect()
+}
+
+/// Seconds since a `timestamp`, `ts` or `time` field of a JSON payload, in seconds or millis.
+fn age_hint(payload: &[u8]) -> Option<u64> {
+    let json: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    let stamp = ["timestamp", "ts", "time"]
+        .iter()
+        .find_map(|key| json.get(key)?.as_f64())?;
+    let secs = if stamp > 1e11 { stamp / 1000.0 } else { stamp };
+    now().checked_sub(secs as u64)
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetainedPanel {
+    filter: String,
+    #[serde(skip)]
+    selected: BTreeSet<String>,
+    #[serde(skip)]
+    dry_run: bool,
+    #[serde(skip)]
+    confirm: bool,
+    #[serde(skip)]
+    status: String,
+}
+
+impl RetainedPanel {
+    /// Shows the scan results, returns the filter when a new scan should start.
+    pub fn ui(&mut self, ui: &mut Ui, id: u32, manager: &mut MqttServerManager) -> Option<String> {
+        let mut start = None;
+        ui.collapsing("Retained messages", |ui| {
+            let running = manager
+                .retained_scan(id)
+                .is_some_and(RetainedScan::is_running);
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("#"));
+                if ui
+                    .add_enabled(!running, egui::Button::new("scan"))
+                    .clicked()
+                {
+                    let filter = match self.filter.is_empty() {
+                        true => String::from("#"),
+                        false => self.filter.clone(),
+                    };
+                    self.selected.clear();
+                    self.confirm = false;
+                    self.status.clear();
+                    start = Some(filter);
+                }
+                if running {
+                    ui.spinner();
+                    ui.ctx().request_repaint_after(Duration::from_millis(200));
+                }
+            });
+            let Some(scan) = manager.retained_scan(id) else {
+                return;
+            };
+            let messages = scan.messages();
+            ui.small(format!(
+                "{} retained messages below '{}', {} bytes",
+                messages.len(),
+                scan.filter(),
+                messages.iter().map(|m| m.size).sum::<usize>()
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("select all").clicked() {
+                    self.selected = messages.iter().map(|m| m.topic.clone()).collect();
+                }
+                if ui.button("select none").clicked() {
+                    self.selected.clear();
+                }
+            });
+            self.selected
+                .retain(|topic| messages.iter().any(|m| &m.topic == topic));
+            TableBuilder::new(ui)
+                .id_salt("retained_messages")
+                .column(Column::auto())
+                .column(Column::auto().at_least(120.0))
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::remainder())
+                .striped(true)
+                .max_scroll_height(250.0)
+                .header(20.0, |mut header| {
+                    for title in ["", "Topic", "Size", "Age", "Payload"] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(16.0, messages.len(), |mut row| {
+                        let message = &messages[row.index()];
+                        row.col(|ui| {
+                            let mut selected = self.selected.contains(&message.topic);
+                            if ui.checkbox(&mut selected, "").changed() {
+                                match selected {
+                                    true => self.selected.insert(message.topic.clone()),
+                                    false => self.selected.remove(&message.topic),
+                                };
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(&message.topic);
+                        });
+                        row.col(|ui| {
+                            ui.label(format!("{} B", message.size));
+                        });
+                        row.col(|ui| {
+                            ui.label(message.age_secs.map(format_age).unwrap_or_default());
+                        });
+                        row.col(|ui| {
+                            ui.small(&message.preview);
+                        });
+                    });
+                });
+            let connected = manager.servers().contains_key(&id);
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.dry_run, "dry run");
+                let enabled = connected && !running && !self.selected.is_empty();
+                if ui
+                    .add_enabled(enabled, egui::Button::new("clear selected"))
+                    .on_disabled_hover_text("select messages of a finished scan while connected")
+                    .clicked()
+                {
+                    self.confirm = true;
+                }
+            });
+            if self.confirm {
+                ui.group(|ui| {
+                    let verb = match self.dry_run {
+                        true => "would clear",
+                        false => "clear",
+                    };
+                    ui.label(format!(
+                        "This {} {} retained messages:",
+                        verb,
+                        self.selected.len()
+                    ));
+                    egui::ScrollArea::vertical()
+                        .id_salt("retained_confirm")
+                        .max_height(100.0)
+                        .show(ui, |ui| {
+                            for topic in &self.selected {
+                                ui.small(topic);
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        if !self.dry_run && ui.button("clear").clicked() {
+                            if let Some(server) = manager.servers().get(&id) {
+                                for topic in &self.selected {
+                                    server.publish(topic.as_str(), vec![], QoS::AtLeastOnce, true);
+                                }
+                                scan.forget(&self.selected);
+                                self.status = format!("cleared {} topics", self.selected.len());
+                                self.selected.clear();
+                            }
+                            self.confirm = false;
+                        }
+                        if ui.button("cancel").clicked() {
+                            self.confirm = false;
+                        }
+                    });
+                });
+            }
+            if !self.status.is_empty() {
+                ui.small(&self.status);
+            }
+        });
+        start
+    }
+}
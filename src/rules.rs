@@ -0,0 +1,687 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use egui::{ahash::HashMap, Color32, Context, Ui};
+use regex::Regex;
+use rumqttc::Publish;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::MqttServer,
+    mqtt_servermanager::MqttServerManager,
+    publish::{format_time, now},
+    topic,
+};
+
+const MAX_ALERTS: usize = 1000;
+/// Freedesktop sound theme name, also understood on macOS as a system sound.
+const ALERT_SOUND: &str = "message-new-instant";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Compare {
+    #[default]
+    Greater,
+    Less,
+    Equal,
+    NotEqual,
+}
+
+impl Compare {
+    const ALL: [Compare; 4] = [
+        Compare::Greater,
+        Compare::Less,
+        Compare::Equal,
+        Compare::NotEqual,
+    ];
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Compare::Greater => ">",
+            Compare::Less => "<",
+            Compare::Equal => "==",
+            Compare::NotEqual => "!=",
+        }
+    }
+
+    fn check(self, left: f64, right: f64) -> bool {
+        match self {
+            Compare::Greater => left > right,
+            Compare::Less => left < right,
+            Compare::Equal => left == right,
+            Compare::NotEqual => left != right,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum Condition {
+    /// A numeric JSON field, addressed by a dot separated path, or the whole payload.
+    Threshold {
+        field: String,
+        compare: Compare,
+        value: f64,
+    },
+    /// No matching message for this long.
+    Silence {
+        secs: u64,
+    },
+    Regex {
+        pattern: String,
+    },
+    /// Any matching message with the retain flag set.
+    Retained,
+}
+
+impl Condition {
+    fn kind(&self) -> &'static str {
+        match self {
+            Condition::Threshold { .. } => "threshold",
+            Condition::Silence { .. } => "silence",
+            Condition::Regex { .. } => "regex",
+            Condition::Retained => "retained",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Rule {
+    pub id: u32,
+    pub name: String,
+    pub enabled: bool,
+    /// `None` applies the rule to every server.
+    pub server: Option<u32>,
+    pub filter: String,
+    pub condition: Condition,
+    pub notify: bool,
+    pub sound: bool,
+    /// Unix time until which the rule raises no alerts.
+    pub muted_until: u64,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            id: fastrand::u32(..),
+            name: String::from("new rule"),
+            enabled: false,
+            server: None,
+            filter: String::from("#"),
+            condition: Condition::Threshold {
+                field: String::new(),
+                compare: Compare::Greater,
+                value: 0.0,
+            },
+            notify: true,
+            sound: false,
+            muted_until: 0,
+        }
+    }
+}
+
+impl Rule {
+    fn covers(&self, server: u32) -> bool {
+        self.enabled && self.server.map_or(true, |id| id == server)
+    }
+}
+
+#[derive(Clone)]
+pub struct Alert {
+    pub id: u64,
+    pub rule: u32,
+    pub rule_name: String,
+    pub server: u32,
+    pub topic: String,
+    pub message: String,
+    /// Unix time of the last occurrence.
+    pub at: u64,
+    pub count: u32,
+    pub acknowledged: bool,
+}
+
+struct RuleRuntime {
+    rule: Rule,
+    regex: Option<Result<Regex, String>>,
+}
+
+/// Evaluates the rules against incoming messages and keeps the raised alerts.
+#[derive(Default)]
+pub struct RuleEngine {
+    rules: Vec<RuleRuntime>,
+    alerts: VecDeque<Alert>,
+    next_alert: u64,
+    last_seen: HashMap<(u32, u32), Instant>,
+    silent: HashSet<(u32, u32)>,
+}
+
+impl RuleEngine {
+    /// Takes over the rules, compiling changed regular expressions.
+    pub fn sync(&mut self, rules: &[Rule]) {
+        if self.rules.len() == rules.len()
+            && self
+                .rules
+                .iter()
+                .zip(rules)
+                .all(|(r, rule)| r.rule == *rule)
+        {
+            return;
+        }
+        self.rules = rules
+            .iter()
+            .map(|rule| RuleRuntime {
+                regex: match &rule.condition {
+                    Condition::Regex { pattern } => {
+                        Some(Regex::new(pattern).map_err(|e| e.to_string()))
+                    }
+                    _ => None,
+                },
+                rule: rule.clone(),
+            })
+            .collect();
+        self.last_seen
+            .retain(|(rule, _), _| rules.iter().any(|r| r.id == *rule));
+        self.silent
+            .retain(|(rule, _)| rules.iter().any(|r| r.id == *rule));
+    }
+
+    pub fn rule_error(&self, id: u32) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|r| r.rule.id == id)
+            .and_then(|r| r.regex.as_ref()?.as_ref().err())
+            .map(String::as_str)
+    }
+
+    /// Enabled filters of rules applying to `server`, which it has to subscribe to.
+    pub fn filters(&self, server: u32) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|r| r.rule.covers(server))
+            .map(|r| r.rule.filter.clone())
+            .collect()
+    }
+
+    pub fn evaluate(&mut self, server: u32, message: &Publish) {
+        let mut raised = vec![];
+        for runtime in &self.rules {
+            let rule = &runtime.rule;
+            if !rule.covers(server) || !topic::matches(&rule.filter, &message.topic) {
+                continue;
+            }
+            self.last_seen.insert((rule.id, server), Instant::now());
+            self.silent.remove(&(rule.id, server));
+            if rule.muted_until > now() {
+                continue;
+            }
+            let text = match (&rule.condition, &runtime.regex) {
+                (
+                    Condition::Threshold {
+                        field,
+                        compare,
+                        value,
+                    },
+                    _,
+                ) => field_value(&message.payload, field)
+                    .filter(|actual| compare.check(*actual, *value))
+                    .map(|actual| {
+                        let field = if field.is_empty() { "payload" } else { field };
+                        format!("{} = {} {} {}", field, actual, compare.symbol(), value)
+                    }),
+                (Condition::Regex { pattern }, Some(Ok(regex))) => regex
+                    .is_match(&String::from_utf8_lossy(&message.payload))
+                    .then(|| format!("payload matches '{}'", pattern)),
+                (Condition::Retained, _) if message.retain => Some(format!(
+                    "retained message of {} bytes",
+                    message.payload.len()
+                )),
+                _ => None,
+            };
+            if let Some(text) = text {
+                raised.push((rule.clone(), text));
+            }
+        }
+        for (rule, text) in raised {
+            self.raise(&rule, server, &message.topic, text);
+        }
+    }
+
+    /// Raises alerts for silence rules of the connected `servers`.
+    pub fn check_silence(&mut self, servers: &[u32]) {
+        // the silence of a server starts over when it reconnects
+        self.last_seen
+            .retain(|(_, server), _| servers.contains(server));
+        let mut raised = vec![];
+        for runtime in &self.rules {
+            let Condition::Silence { secs } = runtime.rule.condition else {
+                continue;
+            };
+            for server in servers {
+                if !runtime.rule.covers(*server) || runtime.rule.muted_until > now() {
+                    continue;
+                }
+                let key = (runtime.rule.id, *server);
+                let last_seen = self.last_seen.entry(key).or_insert_with(Instant::now);
+                if last_seen.elapsed() >= Duration::from_secs(secs) && self.silent.insert(key) {
+                    let text = format!("no message for {} s", secs);
+                    raised.push((runtime.rule.clone(), *server, text));
+                }
+            }
+        }
+        for (rule, server, text) in raised {
+            let filter = rule.filter.clone();
+            self.raise(&rule, server, &filter, text);
+        }
+    }
+
+    pub fn has_silence_rules(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|r| r.rule.enabled && matches!(r.rule.condition, Condition::Silence { .. }))
+    }
+
+    /// Adds an alert, or counts it again while the same one is unacknowledged.
+    fn raise(&mut self, rule: &Rule, server: u32, topic: &str, message: String) {
+        if let Some(alert) = self.alerts.iter_mut().find(|alert| {
+            !alert.acknowledged
+                && alert.rule == rule.id
+                && alert.server == server
+                && alert.topic == topic
+        }) {
+            alert.count += 1;
+            alert.at = now();
+            alert.message = message;
+            return;
+        }
+        if rule.notify {
+            notify(rule, topic, &message);
+        }
+        self.next_alert += 1;
+        self.alerts.push_back(Alert {
+            id: self.next_alert,
+            rule: rule.id,
+            rule_name: rule.name.clone(),
+            server,
+            topic: topic.to_owned(),
+            message,
+            at: now(),
+            count: 1,
+            acknowledged: false,
+        });
+        if self.alerts.len() > MAX_ALERTS {
+            self.alerts.pop_front();
+        }
+    }
+
+    pub fn alerts(&self) -> &VecDeque<Alert> {
+        &self.alerts
+    }
+
+    pub fn unacknowledged(&self) -> usize {
+        self.alerts
+            .iter()
+            .filter(|alert| !alert.acknowledged)
+            .count()
+    }
+
+    pub fn acknowledge(&mut self, id: u64) {
+        if let Some(alert) = self.alerts.iter_mut().find(|alert| alert.id == id) {
+            alert.acknowledged = true;
+        }
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for alert in self.alerts.iter_mut() {
+            alert.acknowledged = true;
+        }
+    }
+
+    pub fn clear_acknowledged(&mut self) {
+        self.alerts.retain(|alert| !alert.acknowledged);
+    }
+}
+
+/// Looks up a dot separated `path` in a JSON payload, an empty path uses the whole payload.
+fn field_value(payload: &[u8], path: &str) -> Option<f64> {
+    let json: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    let value = path
+        .split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(&json, |value, key| match key.parse::<usize>() {
+            Ok(index) if value.is_array() => value.get(index),
+            _ => value.get(key),
+        })?;
+    match value {
+        serde_json::Value::String(text) => text.parse().ok(),
+        value => value.as_f64(),
+    }
+}
+
+fn notify(rule: &Rule, topic: &str, message: &str) {
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .summary(&format!("oldqtt: {}", rule.name))
+        .body(&format!("{}\n{}", topic, message));
+    if rule.sound {
+        notification.sound_name(ALERT_SOUND);
+    }
+    // showing a notification talks to the desktop and may block for a while
+    std::thread::spawn(move || {
+        if let Err(e) = notification.show() {
+            log::error!("Cannot show notification: {}", e);
+        }
+    });
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RulesWindow {
+    #[serde(skip)]
+    pub open: bool,
+    rules: Vec<Rule>,
+    #[serde(skip)]
+    show_acknowledged: bool,
+}
+
+impl RulesWindow {
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        manager: &mut MqttServerManager,
+        servers: &HashMap<u32, MqttServer>,
+    ) {
+        let server_name = |id: u32| {
+            servers
+                .get(&id)
+                .map(MqttServer::name)
+                .unwrap_or_else(|| String::from("-"))
+        };
+        egui::Window::new("Rules & Alerts")
+            .open(&mut self.open)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+                let engine = manager.rules_mut();
+                ui.horizontal(|ui| {
+                    ui.heading(format!("Alerts ({})", engine.unacknowledged()));
+                    if ui.button("acknowledge all").clicked() {
+                        engine.acknowledge_all();
+                    }
+                    if ui.button("clear acknowledged").clicked() {
+                        engine.clear_acknowledged();
+                    }
+                    ui.checkbox(&mut self.show_acknowledged, "show acknowledged");
+                });
+                let mut acknowledge = None;
+                egui::ScrollArea::vertical()
+                    .id_salt("alerts")
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("alerts").striped(true).show(ui, |ui| {
+                            for alert in engine.alerts().iter().rev() {
+                                if alert.acknowledged && !self.show_acknowledged {
+                                    continue;
+                                }
+                                let color = match alert.acknowledged {
+                                    true => ui.visuals().weak_text_color(),
+                                    false => Color32::RED,
+                                };
+                                ui.colored_label(color, format_time(alert.at));
+                                ui.label(server_name(alert.server));
+                                ui.label(&alert.rule_name);
+                                ui.label(&alert.topic);
+                                ui.label(match alert.count {
+                                    1 => alert.message.clone(),
+                                    count => format!("{} ({}x)", alert.message, count),
+                                });
+                                if !alert.acknowledged && ui.button("ack").clicked() {
+                                    acknowledge = Some(alert.id);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+                if let Some(id) = acknowledge {
+                    engine.acknowledge(id);
+                }
+                ui.separator();
+                ui.heading("Rules");
+                let mut delete = None;
+                for (index, rule) in self.rules.iter_mut().enumerate() {
+                    ui.push_id(rule.id, |ui| {
+                        rule_ui(ui, rule, servers, &server_name);
+                        if let Some(error) = manager.rules_mut().rule_error(rule.id) {
+                            ui.colored_label(Color32::RED, error);
+                        }
+                        if ui.button("Del").clicked() {
+                            delete = Some(index);
+                        }
+                    });
+                    ui.separator();
+                }
+                if let Some(index) = delete {
+                    self.rules.remove(index);
+                }
+                if ui.button("Add").clicked() {
+                    self.rules.push(Rule::default());
+                }
+            });
+        manager.rules_mut().sync(&self.rules);
+        if manager.rules_mut().has_silence_rules() {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+}
+
+fn rule_ui(
+    ui: &mut Ui,
+    rule: &mut Rule,
+    servers: &HashMap<u32, MqttServer>,
+    server_name: &dyn Fn(u32) -> String,
+) {
+    egui::Grid::new("rule").show(ui, |ui| {
+        ui.checkbox(&mut rule.enabled, "enabled");
+        ui.text_edit_singleline(&mut rule.name);
+        ui.end_row();
+        ui.label("server");
+        egui::ComboBox::from_id_salt("rule_server")
+            .selected_text(rule.server.map(server_name).unwrap_or("all".into()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut rule.server, None, "all");
+                for (id, server) in servers.iter() {
+                    ui.selectable_value(&mut rule.server, Some(*id), server.name());
+                }
+            });
+        ui.end_row();
+        ui.label("filter");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut rule.filter);
+            if let Err(e) = topic::validate_filter(&rule.filter) {
+                ui.colored_label(Color32::RED, e);
+            }
+        });
+        ui.end_row();
+        ui.label("condition");
+        egui::ComboBox::from_id_salt("rule_condition")
+            .selected_text(rule.condition.kind())
+            .show_ui(ui, |ui| {
+                let conditions = [
+                    Condition::Threshold {
+                        field: String::new(),
+                        compare: Compare::Greater,
+                        value: 0.0,
+                    },
+                    Condition::Silence { secs: 60 },
+                    Condition::Regex {
+                        pattern: String::new(),
+                    },
+                    Condition::Retained,
+                ];
+                for condition in conditions {
+                    let selected = rule.condition.kind() == condition.kind();
+                    if ui.selectable_label(selected, condition.kind()).clicked() && !selected {
+                        rule.condition = condition;
+                    }
+                }
+            });
+        ui.horizontal(|ui| match &mut rule.condition {
+            Condition::Threshold {
+                field,
+                compare,
+                value,
+            } => {
+                ui.add(
+                    egui::TextEdit::singleline(field)
+                        .hint_text("json.field")
+                        .desired_width(100.0),
+                );
+                egui::ComboBox::from_id_salt("rule_compare")
+                    .width(40.0)
+                    .selected_text(compare.symbol())
+                    .show_ui(ui, |ui| {
+                        for option in Compare::ALL {
+                            ui.selectable_value(compare, option, option.symbol());
+                        }
+                    });
+                ui.add(egui::DragValue::new(value));
+            }
+            Condition::Silence { secs } => {
+                ui.add(egui::DragValue::new(secs).range(1..=86400).suffix(" s"));
+            }
+            Condition::Regex { pattern } => {
+                ui.add(egui::TextEdit::singleline(pattern).hint_text("regex"));
+            }
+            Condition::Retained => {}
+        });
+        ui.end_row();
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut rule.notify, "desktop notification");
+        ui.add_enabled(rule.notify, egui::Checkbox::new(&mut rule.sound, "sound"));
+        if rule.muted_until > now() {
+            ui.small(format!("muted until {}", format_time(rule.muted_until)));
+            if ui.button("unmute").clicked() {
+                rule.muted_until = 0;
+            }
+        } else {
+            if ui.button("mute 15 min").clicked() {
+                rule.muted_until = now() + 15 * 60;
+            }
+            if ui.button("mute 1 h").clicked() {
+                rule.muted_until = now() + 60 * 60;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_engine(filter: &str, condition: Condition) -> RuleEngine {
+        let mut engine = RuleEngine::default();
+        engine.sync(&[Rule {
+            id: 1,
+            enabled: true,
+            filter: filter.to_owned(),
+            condition,
+            notify: false,
+            ..Rule::default()
+        }]);
+        engine
+    }
+
+    fn message(topic: &str, payload: &str) -> Publish {
+        Publish::new(topic, rumqttc::QoS::AtMostOnce, payload)
+    }
+
+    #[test]
+    fn threshold_crossing_and_rearm() {
+        let mut engine = rule_engine(
+            "sensors/+",
+            Condition::Threshold {
+                field: String::from("temp.value"),
+                compare: Compare::Greater,
+                value: 30.0,
+            },
+        );
+        engine.evaluate(1, &message("sensors/a", r#"{"temp": {"value": 20}}"#));
+        engine.evaluate(1, &message("other", r#"{"temp": {"value": 40}}"#));
+        engine.evaluate(1, &message("sensors/a", "not json"));
+        assert!(engine.alerts().is_empty());
+
+        engine.evaluate(1, &message("sensors/a", r#"{"temp": {"value": 35}}"#));
+        engine.evaluate(1, &message("sensors/a", r#"{"temp": {"value": "36"}}"#));
+        assert_eq!(engine.alerts().len(), 1);
+        assert_eq!(engine.alerts()[0].count, 2);
+        assert_eq!(engine.alerts()[0].message, "temp.value = 36 > 30");
+
+        // acknowledging re-arms the rule for the next crossing
+        engine.acknowledge_all();
+        engine.evaluate(1, &message("sensors/a", r#"{"temp": {"value": 10}}"#));
+        assert_eq!(engine.unacknowledged(), 0);
+        engine.evaluate(1, &message("sensors/a", r#"{"temp": {"value": 31}}"#));
+        assert_eq!(engine.alerts().len(), 2);
+        assert_eq!(engine.unacknowledged(), 1);
+    }
+
+    #[test]
+    fn muted_rules_stay_quiet() {
+        let mut engine = RuleEngine::default();
+        engine.sync(&[Rule {
+            id: 1,
+            enabled: true,
+            notify: false,
+            muted_until: now() + 60,
+            condition: Condition::Retained,
+            ..Rule::default()
+        }]);
+        let mut retained = message("a", "x");
+        retained.retain = true;
+        engine.evaluate(1, &retained);
+        assert!(engine.alerts().is_empty());
+    }
+
+    #[test]
+    fn silence_timeout() {
+        let mut engine = rule_engine("heartbeat", Condition::Silence { secs: 0 });
+        engine.check_silence(&[1]);
+        assert_eq!(engine.alerts().len(), 1);
+        assert_eq!(engine.alerts()[0].message, "no message for 0 s");
+        // stays silent without raising again
+        engine.check_silence(&[1]);
+        assert_eq!(engine.alerts()[0].count, 1);
+        // a message ends the silence, the next one raises again
+        engine.evaluate(1, &message("heartbeat", ""));
+        engine.check_silence(&[1]);
+        assert_eq!(engine.alerts()[0].count, 2);
+
+        let mut engine = rule_engine("heartbeat", Condition::Silence { secs: 60 });
+        engine.check_silence(&[1]);
+        assert!(engine.alerts().is_empty());
+    }
+
+    #[test]
+    fn regex_match() {
+        let mut engine = rule_engine(
+            "#",
+            Condition::Regex {
+                pattern: String::from("^ERR-[0-9]+$"),
+            },
+        );
+        engine.evaluate(1, &message("log", "OK"));
+        engine.evaluate(1, &message("log", "ERR-x"));
+        assert!(engine.alerts().is_empty());
+        engine.evaluate(1, &message("log", "ERR-42"));
+        assert_eq!(engine.alerts().len(), 1);
+        assert_eq!(engine.alerts()[0].topic, "log");
+
+        let engine = rule_engine(
+            "#",
+            Condition::Regex {
+                pattern: String::from("("),
+            },
+        );
+        assert!(engine.rule_error(1).is_some());
+    }
+}
@@ -0,0 +1,458 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mqtt_servermanager::{MqttServerManager, Publisher},
+    publish::PublishTemplate,
+};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum Schedule {
+    /// Publish every `ms` milliseconds until stopped.
+    Every { ms: u64 },
+    /// Publish `count` times, waiting `delay_ms` in between.
+    Times { count: u64, delay_ms: u64 },
+    /// Five field cron expression, evaluated in UTC.
+    Cron(String),
+}
+
+impl Schedule {
+    fn describe(&self) -> String {
+        match self {
+            Schedule::Every { ms } => format!("every {} ms", ms),
+            Schedule::Times { count, delay_ms } => {
+                format!("{} times, {} ms apart", count, delay_ms)
+            }
+            Schedule::Cron(expression) => format!("cron '{}' (UTC)", expression),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScheduledPublish {
+    pub id: u32,
+    pub message: PublishTemplate,
+    pub schedule: Schedule,
+}
+
+/// A schedule publishing on its own thread, stopped when dropped.
+pub struct RunningSchedule {
+    stop: Option<Sender<()>>,
+    handle: JoinHandle<()>,
+    sent: Arc<AtomicU64>,
+    failed: Arc<AtomicU64>,
+}
+
+impl RunningSchedule {
+    pub fn start(publisher: Publisher, scheduled: ScheduledPublish) -> Result<Self, String> {
+        let cron = match &scheduled.schedule {
+            Schedule::Cron(expression) => Some(Cron::parse(expression)?),
+            _ => None,
+        };
+        let (stop_tx, stop_rx) = std::sync::mpsc::channel();
+        let sent = Arc::new(AtomicU64::new(0));
+        let failed = Arc::new(AtomicU64::new(0));
+        let (sent_c, failed_c) = (sent.clone(), failed.clone());
+        let handle = std::thread::spawn(move || {
+            let publish = || {
+                match publisher.send(&scheduled.message) {
+                    Ok(()) => sent_c.fetch_add(1, Ordering::Relaxed),
+                    Err(e) => {
+                        log::error!("Scheduled publish '{}' failed: {:?}", scheduled.id, e);
+                        failed_c.fetch_add(1, Ordering::Relaxed)
+                    }
+                };
+            };
+            match (&scheduled.schedule, cron) {
+                (Schedule::Every { ms }, _) => loop {
+                    publish();
+                    if !wait(&stop_rx, Duration::from_millis(*ms)) {
+                        return;
+                    }
+                },
+                (Schedule::Times { count, delay_ms }, _) => {
+                    for i in 0..*count {
+                        if i > 0 && !wait(&stop_rx, Duration::from_millis(*delay_ms)) {
+                            return;
+                        }
+                        publish();
+                    }
+                }
+                (Schedule::Cron(_), Some(cron)) => loop {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let to_next_minute = Duration::from_secs(60 - now.as_secs() % 60)
+                        - Duration::from_nanos(now.subsec_nanos() as u64);
+                    if !wait(&stop_rx, to_next_minute) {
+                        return;
+                    }
+                    if cron.matches(now.as_secs() / 60 + 1) {
+                        publish();
+                    }
+                },
+                (Schedule::Cron(_), None) => {}
+            }
+        });
+        Ok(RunningSchedule {
+            stop: Some(stop_tx),
+            handle,
+            sent,
+            failed,
+        })
+    }
+
+    pub fn stop(&mut self) {
+        self.stop = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// Sleeps for `duration`, returns false if the schedule got stopped meanwhile.
+fn wait(stop: &Receiver<()>, duration: Duration) -> bool {
+    matches!(stop.recv_timeout(duration), Err(RecvTimeoutError::Timeout))
+}
+
+/// Minimal cron matcher supporting `*`, `*/n`, `a-b`, `a-b/n` and lists per field.
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Restricted day of month and day of week fields match if either does.
+    either_day: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(format!("expected 5 cron fields, got {}", fields.len()));
+        };
+        let either_day = !days.starts_with('*') && !weekdays.starts_with('*');
+        let weekdays = parse_field(weekdays, 0, 7)?;
+        Ok(Cron {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            // both 0 and 7 mean sunday
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day,
+        })
+    }
+
+    /// Whether the schedule fires in the given minute since the unix epoch.
+    fn matches(&self, minute: u64) -> bool {
+        let days = minute / 60 / 24;
+        let (month, day) = month_day(days as i64);
+        let weekday = (days + 4) % 7;
+        let day_matches = self.days & 1 << day != 0;
+        let weekday_matches = self.weekdays & 1 << weekday != 0;
+        self.minutes & 1 << (minute % 60) != 0
+            && self.hours & 1 << (minute / 60 % 24) != 0
+            && self.months & 1 << month != 0
+            && match self.either_day {
+                true => day_matches || weekday_matches,
+                false => day_matches && weekday_matches,
+            }
+    }
+}
+
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse().map_err(|_| format!("bad step '{}'", part))?,
+            ),
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (parse_value(from)?, parse_value(to)?),
+            None => (parse_value(range)?, parse_value(range)?),
+        };
+        if from < min || to > max || from > to || step == 0 {
+            return Err(format!("'{}' out of range {}-{}", part, min, max));
+        }
+        for value in (from..=to).step_by(step) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("bad value '{}'", value))
+}
+
+/// Month and day of month for days since the unix epoch.
+fn month_day(days: i64) -> (u64, u64) {
+    let days = days + 719_468;
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    (month as u64, day as u64)
+}
+
+/// Persisted schedules of a server, running state lives in the manager.
+#[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulePanel {
+    schedules: Vec<ScheduledPublish>,
+    #[serde(skip)]
+    kind: usize,
+    #[serde(skip)]
+    interval_ms: u64,
+    #[serde(skip)]
+    count: u64,
+    #[serde(skip)]
+    cron: String,
+    #[serde(skip)]
+    error: String,
+}
+
+impl SchedulePanel {
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        id: u32,
+        manager: &mut MqttServerManager,
+        message: &PublishTemplate,
+    ) {
+        ui.collapsing(format!("Scheduled ({})", self.schedules.len()), |ui| {
+            let mut delete = None;
+            for scheduled in self.schedules.iter() {
+                let state = manager
+                    .schedule(id, scheduled.id)
+                    .map(|running| (running.is_running(), running.sent(), running.failed()));
+                let connected = manager.servers().contains_key(&id);
+                ui.horizontal(|ui| {
+                    if let Some((true, _, _)) = state {
+                        if ui.button("stop").clicked() {
+                            manager.stop_schedule(id, scheduled.id);
+                        }
+                    } else if ui
+                        .add_enabled(connected, egui::Button::new("start"))
+                        .clicked()
+                    {
+                        self.error = manager
+                            .start_schedule(id, scheduled)
+                            .err()
+                            .unwrap_or_default();
+                    }
+                    if let Some((_, sent, failed)) = state {
+                        ui.small(format!("sent {} failed {}", sent, failed));
+                    }
+                    if ui.button("del").clicked() {
+                        delete = Some(scheduled.id);
+                    }
+                    ui.label(&scheduled.message.topic);
+                    ui.small(scheduled.schedule.describe());
+                });
+            }
+            if let Some(schedule_id) = delete {
+                manager.remove_schedule(id, schedule_id);
+                self.schedules.retain(|s| s.id != schedule_id);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("schedule_kind")
+                    .selected_text(["every", "times", "cron"][self.kind])
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.kind, 0, "every");
+                        ui.selectable_value(&mut self.kind, 1, "times");
+                        ui.selectable_value(&mut self.kind, 2, "cron");
+                    });
+                match self.kind {
+                    0 => {
+                        ui.add(
+                            egui::DragValue::new(&mut self.interval_ms)
+                                .range(1..=u64::MAX)
+                                .suffix(" ms"),
+                        );
+                    }
+                    1 => {
+                        ui.add(
+                            egui::DragValue::new(&mut self.count)
+                                .range(1..=u64::MAX)
+                                .suffix(" x"),
+                        );
+                        ui.add(egui::DragValue::new(&mut self.interval_ms).suffix(" ms"));
+                    }
+                    _ => {
+                        ui.add(egui::TextEdit::singleline(&mut self.cron).hint_text("*/5 * * * *"));
+                    }
+                }
+                if ui.button("schedule publish form").clicked() {
+                    let schedule = match self.kind {
+                        0 => Schedule::Every {
+                            ms: self.interval_ms.max(1),
+                        },
+                        1 => Schedule::Times {
+                            count: self.count.max(1),
+                            delay_ms: self.interval_ms,
+                        },
+                        _ => Schedule::Cron(self.cron.clone()),
+                    };
+                    self.error = match &schedule {
+                        Schedule::Cron(expression) => {
+                            Cron::parse(expression).err().unwrap_or_default()
+                        }
+                        _ => String::new(),
+                    };
+                    if self.error.is_empty() {
+                        self.schedules.push(ScheduledPublish {
+                            id: fastrand::u32(..),
+                            message: message.clone(),
+                            schedule,
+                        });
+                    }
+                }
+            });
+            if !self.error.is_empty() {
+                ui.colored_label(egui::Color32::RED, &self.error);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minute since the unix epoch of a UTC date and time.
+    fn minute(year: i64, month: i64, day: i64, hour: u64, min: u64) -> u64 {
+        let days = (1970..year)
+            .map(|y| if is_leap(y) { 366 } else { 365 })
+            .sum::<i64>()
+            + (1..month)
+                .map(|m| match m {
+                    2 if is_leap(year) => 29,
+                    2 => 28,
+                    4 | 6 | 9 | 11 => 30,
+                    _ => 31,
+                })
+                .sum::<i64>()
+            + day
+            - 1;
+        days as u64 * 24 * 60 + hour * 60 + min
+    }
+
+    fn is_leap(year: i64) -> bool {
+        year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field("*", 1, 3), Ok(0b1110));
+        assert_eq!(parse_field("2-4", 0, 59), Ok(0b11100));
+        assert_eq!(parse_field("1,3", 0, 59), Ok(0b1010));
+        assert_eq!(
+            parse_field("*/15", 0, 59),
+            Ok(1 | 1 << 15 | 1 << 30 | 1 << 45)
+        );
+        assert_eq!(
+            parse_field("10-20/5", 0, 59),
+            Ok(1 << 10 | 1 << 15 | 1 << 20)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_fields() {
+        for field in ["60", "0", "5-3", "*/0", "a", "1-", "*/x", ""] {
+            assert!(parse_field(field, 1, 59).is_err(), "{}", field);
+        }
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("* * 32 * *").is_err());
+        assert!(Cron::parse("* * * 13 *").is_err());
+        assert!(Cron::parse("* * * * 8").is_err());
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        let sunday = Cron::parse("* * * * 0").unwrap().weekdays;
+        assert_eq!(sunday, 1);
+        assert_eq!(Cron::parse("* * * * 7").unwrap().weekdays, sunday);
+        assert_eq!(Cron::parse("* * * * 5-7").unwrap().weekdays, 0b1100001);
+    }
+
+    #[test]
+    fn month_days() {
+        assert_eq!(month_day(0), (1, 1));
+        assert_eq!(month_day(-1), (12, 31));
+        // 2024 is a leap year, 2100 isn't, 2000 is
+        assert_eq!(month_day(19_782), (2, 29));
+        assert_eq!(month_day(19_783), (3, 1));
+        assert_eq!(month_day(47_540), (2, 28));
+        assert_eq!(month_day(47_541), (3, 1));
+        assert_eq!(month_day(11_016), (2, 29));
+        for days in [0, 19_782, 47_541, 11_016] {
+            let (month, day) = month_day(days);
+            let year = (1970..)
+                .find(|y| minute(y + 1, 1, 1, 0, 0) / 1440 > days as u64)
+                .unwrap();
+            assert_eq!(
+                minute(year, month as i64, day as i64, 0, 0) / 1440,
+                days as u64
+            );
+        }
+    }
+
+    #[test]
+    fn matches_minutes() {
+        let cron = Cron::parse("30 8 * * *").unwrap();
+        assert!(cron.matches(minute(2024, 6, 3, 8, 30)));
+        assert!(!cron.matches(minute(2024, 6, 3, 8, 31)));
+        assert!(!cron.matches(minute(2024, 6, 3, 9, 30)));
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // the 13th, or any friday; 2024-09-13 was a friday
+        let cron = Cron::parse("0 0 13 * 5").unwrap();
+        assert!(cron.matches(minute(2024, 9, 13, 0, 0)));
+        assert!(cron.matches(minute(2024, 9, 6, 0, 0)));
+        assert!(cron.matches(minute(2024, 10, 13, 0, 0)));
+        assert!(!cron.matches(minute(2024, 10, 14, 0, 0)));
+        // with either field unrestricted the other one decides
+        let cron = Cron::parse("0 0 * * 5").unwrap();
+        assert!(cron.matches(minute(2024, 9, 6, 0, 0)));
+        assert!(!cron.matches(minute(2024, 10, 13, 0, 0)));
+        let cron = Cron::parse("0 0 13 * *").unwrap();
+        assert!(!cron.matches(minute(2024, 9, 6, 0, 0)));
+        assert!(cron.matches(minute(2024, 10, 13, 0, 0)));
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use egui::{ahash::HashMap, Context};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST};
+use rumqttc::{Publish, QoS};
+use serde::{Deserialize, Serialize};
+
+use crate::{app::MqttServer, mqtt_servermanager::MqttServerManager, publish::now_us, topic};
+
+/// Keeps runaway scripts from freezing the UI.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_LOG_LINES: usize = 500;
+
+const EXAMPLE: &str = r#"// on_message(filter, |topic, payload| ...), publish(topic, payload[, qos, retain]),
+// subscribe(filter), every(ms, || ...), after(ms, || ...), check(ok, message),
+// from_json(text), to_json(value), now_ms(), print(value)
+on_message("devices/+/ping", |topic, payload| {
+    let reply = topic;
+    reply.replace("/ping", "/pong");
+    publish(reply, payload);
+});
+"#;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScriptConfig {
+    pub id: u32,
+    pub name: String,
+    /// Server the script receives messages from and publishes to.
+    pub server: u32,
+    pub source: String,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        ScriptConfig {
+            id: fastrand::u32(..),
+            name: String::from("new script"),
+            server: 0,
+            source: String::from(EXAMPLE),
+        }
+    }
+}
+
+struct Timer {
+    interval: Option<Duration>,
+    next: Instant,
+    callback: FnPtr,
+}
+
+/// Everything the functions registered with the engine work on.
+#[derive(Default)]
+struct ScriptState {
+    handlers: Vec<(String, FnPtr)>,
+    timers: Vec<Timer>,
+    filters: Vec<String>,
+    outbox: Vec<(String, Vec<u8>, QoS, bool)>,
+    log: VecDeque<String>,
+    passed: u64,
+    failed: u64,
+}
+
+impl ScriptState {
+    fn log(&mut self, line: String) {
+        self.log.push_back(line);
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+}
+
+/// A compiled script with its handlers and timers, driven by the manager.
+pub struct ScriptRuntime {
+    pub server: u32,
+    engine: Engine,
+    ast: AST,
+    state: Rc<RefCell<ScriptState>>,
+}
+
+impl ScriptRuntime {
+    pub fn start(config: &ScriptConfig) -> Self {
+        let state = Rc::new(RefCell::new(ScriptState::default()));
+        let engine = engine(&state);
+        let ast = match engine.compile(&config.source) {
+            Ok(ast) => ast,
+            Err(e) => {
+                state.borrow_mut().log(format!("error: {}", e));
+                AST::empty()
+            }
+        };
+        if let Err(e) = engine.run_ast(&ast) {
+            state.borrow_mut().log(format!("error: {}", e));
+        }
+        ScriptRuntime {
+            server: config.server,
+            engine,
+            ast,
+            state,
+        }
+    }
+
+    /// Calls the handlers whose filter matches `message`.
+    pub fn dispatch(&mut self, message: &Publish) {
+        let handlers: Vec<FnPtr> = self
+            .state
+            .borrow()
+            .handlers
+            .iter()
+            .filter(|(filter, _)| topic::matches(filter, &message.topic))
+            .map(|(_, callback)| callback.clone())
+            .collect();
+        let payload = String::from_utf8_lossy(&message.payload).into_owned();
+        for callback in handlers {
+            self.call(&callback, (message.topic.clone(), payload.clone()));
+        }
+    }
+
+    /// Runs due timers.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        let mut due = vec![];
+        {
+            let mut state = self.state.borrow_mut();
+            for timer in state.timers.iter_mut().filter(|timer| timer.next <= now) {
+                due.push(timer.callback.clone());
+                if let Some(interval) = timer.interval {
+                    timer.next = (timer.next + interval).max(now);
+                }
+            }
+            state
+                .timers
+                .retain(|timer| timer.interval.is_some() || timer.next > now);
+        }
+        for callback in due {
+            self.call(&callback, ());
+        }
+    }
+
+    fn call(&self, callback: &FnPtr, args: impl rhai::FuncArgs) {
+        if let Err(e) = callback.call::<Dynamic>(&self.engine, &self.ast, args) {
+            self.state.borrow_mut().log(format!("error: {}", e));
+        }
+    }
+
+    pub fn has_timers(&self) -> bool {
+        !self.state.borrow().timers.is_empty()
+    }
+
+    /// Filters the script needs its server to subscribe to.
+    pub fn filters(&self) -> Vec<String> {
+        let state = self.state.borrow();
+        let handlers = state.handlers.iter().map(|(filter, _)| filter);
+        let mut filters: Vec<String> = handlers.chain(&state.filters).cloned().collect();
+        filters.sort();
+        filters.dedup();
+        filters
+    }
+
+    pub fn take_outbox(&mut self) -> Vec<(String, Vec<u8>, QoS, bool)> {
+        std::mem::take(&mut self.state.borrow_mut().outbox)
+    }
+
+    pub fn log(&self, line: String) {
+        self.state.borrow_mut().log(line);
+    }
+
+    pub fn log_lines(&self) -> Vec<String> {
+        self.state.borrow().log.iter().cloned().collect()
+    }
+
+    /// Passed and failed `check` calls.
+    pub fn checks(&self) -> (u64, u64) {
+        let state = self.state.borrow();
+        (state.passed, state.failed)
+    }
+}
+
+fn engine(state: &Rc<RefCell<ScriptState>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let s = state.clone();
+    engine.on_print(move |text| s.borrow_mut().log(text.to_owned()));
+    let s = state.clone();
+    engine.on_debug(move |text, _, position| {
+        s.borrow_mut().log(format!("{} {}", position, text));
+    });
+    let s = state.clone();
+    engine.register_fn("on_message", move |filter: &str, callback: FnPtr| {
+        let mut state = s.borrow_mut();
+        match topic::validate_filter(filter) {
+            Ok(()) => state.handlers.push((filter.to_owned(), callback)),
+            Err(e) => state.log(format!("on_message: invalid filter '{}': {}", filter, e)),
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("subscribe", move |filter: &str| {
+        let mut state = s.borrow_mut();
+        match topic::validate_filter(filter) {
+            Ok(()) => state.filters.push(filter.to_owned()),
+            Err(e) => state.log(format!("subscribe: invalid filter '{}': {}", filter, e)),
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("publish", move |topic: &str, payload: &str| {
+        let message = (topic.to_owned(), payload.into(), QoS::AtMostOnce, false);
+        s.borrow_mut().outbox.push(message);
+    });
+    let s = state.clone();
+    engine.register_fn(
+        "publish",
+        move |topic: &str, payload: &str, qos: i64, retain: bool| {
+            let qos = rumqttc::qos(qos as u8).unwrap_or(QoS::AtMostOnce);
+            let message = (topic.to_owned(), payload.into(), qos, retain);
+            s.borrow_mut().outbox.push(message);
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("every", move |ms: i64, callback: FnPtr| {
+        let interval = Duration::from_millis(ms.max(1) as u64);
+        s.borrow_mut().timers.push(Timer {
+            interval: Some(interval),
+            next: Instant::now() + interval,
+            callback,
+        });
+    });
+    let s = state.clone();
+    engine.register_fn("after", move |ms: i64, callback: FnPtr| {
+        s.borrow_mut().timers.push(Timer {
+            interval: None,
+            next: Instant::now() + Duration::from_millis(ms.max(0) as u64),
+            callback,
+        });
+    });
+    let s = state.clone();
+    engine.register_fn("check", move |ok: bool, message: &str| {
+        let mut state = s.borrow_mut();
+        match ok {
+            true => state.passed += 1,
+            false => {
+                state.failed += 1;
+                state.log(format!("check failed: {}", message));
+            }
+        }
+    });
+    engine.register_fn(
+        "from_json",
+        |text: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+            let value: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+            rhai::serde::to_dynamic(value)
+        },
+    );
+    engine.register_fn(
+        "to_json",
+        |value: Dynamic| -> Result<String, Box<EvalAltResult>> {
+            let value: serde_json::Value = rhai::serde::from_dynamic(&value)?;
+            Ok(value.to_string())
+        },
+    );
+    engine.register_fn("now_ms", || (now_us() / 1000) as i64);
+    engine
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptsWindow {
+    #[serde(skip)]
+    pub open: bool,
+    scripts: Vec<ScriptConfig>,
+    selected: Option<u32>,
+}
+
+impl ScriptsWindow {
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        manager: &mut MqttServerManager,
+        servers: &HashMap<u32, MqttServer>,
+    ) {
+        let server_name = |id: u32| {
+            servers
+                .get(&id)
+                .map(MqttServer::name)
+                .unwrap_or_else(|| String::from("-"))
+        };
+        egui::Window::new("Scripts")
+            .open(&mut self.open)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for script in &self.scripts {
+                        let running = manager.script(script.id).is_some();
+                        let label = match running {
+                            true => format!("{} (running)", script.name),
+                            false => script.name.clone(),
+                        };
+                        if ui
+                            .selectable_label(self.selected == Some(script.id), label)
+                            .clicked()
+                        {
+                            self.selected = Some(script.id);
+                        }
+                    }
+                    if ui.button("New").clicked() {
+                        let script = ScriptConfig::default();
+                        self.selected = Some(script.id);
+                        self.scripts.push(script);
+                    }
+                });
+                ui.separator();
+                let Some(script) = self
+                    .scripts
+                    .iter_mut()
+                    .find(|script| Some(script.id) == self.selected)
+                else {
+                    return;
+                };
+                let mut delete = false;
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut script.name);
+                    egui::ComboBox::from_id_salt("script_server")
+                        .selected_text(server_name(script.server))
+                        .show_ui(ui, |ui| {
+                            for (id, server) in servers.iter() {
+                                ui.selectable_value(&mut script.server, *id, server.name());
+                            }
+                        });
+                    if ui.button("Run").clicked() {
+                        manager.start_script(script);
+                    }
+                    if ui
+                        .add_enabled(
+                            manager.script(script.id).is_some(),
+                            egui::Button::new("Stop"),
+                        )
+                        .clicked()
+                    {
+                        manager.stop_script(script.id);
+                    }
+                    if ui.button("Delete").clicked() {
+                        delete = true;
+                    }
+                });
+                ui.add(
+                    egui::TextEdit::multiline(&mut script.source)
+                        .code_editor()
+                        .desired_rows(15)
+                        .desired_width(f32::INFINITY),
+                );
+                if let Some(runtime) = manager.script(script.id) {
+                    let (passed, failed) = runtime.checks();
+                    if passed + failed > 0 {
+                        ui.small(format!("checks: {} passed, {} failed", passed, failed));
+                    }
+                    egui::ScrollArea::vertical()
+                        .max_height(150.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for line in runtime.log_lines() {
+                                ui.monospace(line);
+                            }
+                        });
+                }
+                if delete {
+                    manager.stop_script(script.id);
+                    let id = script.id;
+                    self.scripts.retain(|script| script.id != id);
+                    self.selected = None;
+                }
+            });
+        if manager.has_script_timers() {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{collections::HashMap, error::Error, fmt::Write, fs, path::PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+
+const KEYRING_SERVICE: &str = "oldqtt";
+const VAULT_FILE: &str = "secrets.vault";
+/// XChaCha20 nonces are 24 bytes.
+const NONCE_LEN: usize = 24;
+
+/// Reference to a secret, persisted in place of the secret itself.
+#[derive(Default, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum SecretRef {
+    #[default]
+    None,
+    Keyring(String),
+    Vault(String),
+}
+
+/// On-disk layout of the encrypted fallback store.
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+/// Stores secrets in the OS keyring, falling back to a file encrypted with a master password.
+pub struct SecretStore {
+    vault_path: Option<PathBuf>,
+    master_password: Option<String>,
+    vault: HashMap<String, String>,
+    /// Whether to try the OS keyring before the file.
+    use_keyring: bool,
+}
+
+impl SecretStore {
+    pub fn new() -> Self {
+        SecretStore {
+            vault_path: eframe::storage_dir(KEYRING_SERVICE).map(|dir| dir.join(VAULT_FILE)),
+            master_password: None,
+            vault: HashMap::new(),
+            use_keyring: true,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.master_password.is_none()
+    }
+
+    pub fn vault_exists(&self) -> bool {
+        self.vault_path.as_ref().is_some_and(|path| path.exists())
+    }
+
+    /// Decrypts the vault with the given master password, or creates an empty one.
+    pub fn unlock(&mut self, master_password: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = self.vault_path.as_ref().filter(|path| path.exists()) {
+            let file: VaultFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+            let key = derive_key(master_password, &from_hex(&file.salt)?)?;
+            let nonce = from_hex(&file.nonce)?;
+            if nonce.len() != NONCE_LEN {
+                return Err("corrupt vault: bad nonce".into());
+            }
+            let cipher = XChaCha20Poly1305::new(&key.into());
+            let plain = cipher
+                .decrypt(XNonce::from_slice(&nonce), &*from_hex(&file.data)?)
+                .map_err(|_| "wrong master password or corrupt vault")?;
+            self.vault = serde_json::from_slice(&plain)?;
+        }
+        self.master_password = Some(master_password.to_owned());
+        Ok(())
+    }
+
+    /// Stores a secret under `key`, preferring the OS keyring.
+    pub fn store(&mut self, key: &str, secret: &str) -> Result<SecretRef, Box<dyn Error>> {
+        if self.use_keyring {
+            match keyring::Entry::new(KEYRING_SERVICE, key).and_then(|e| e.set_password(secret)) {
+                Ok(()) => {
+                    if self.vault.remove(key).is_some() {
+                        self.write_vault()?;
+                    }
+                    return Ok(SecretRef::Keyring(key.to_owned()));
+                }
+                // the keyring may only be unavailable for now, so the next secret
+                // tries it again
+                Err(e) => log::warn!("Keyring unavailable, using encrypted file: '{}'", e),
+            }
+        }
+        if self.is_locked() {
+            return Err("secret vault is locked".into());
+        }
+        self.vault.insert(key.to_owned(), secret.to_owned());
+        self.write_vault()?;
+        Ok(SecretRef::Vault(key.to_owned()))
+    }
+
+    /// Resolves a reference, `None` if it points to a locked vault.
+    pub fn load(&self, secret: &SecretRef) -> Result<Option<String>, Box<dyn Error>> {
+        match secret {
+            SecretRef::None => Ok(Some(String::new())),
+            SecretRef::Keyring(key) => Ok(Some(
+                keyring::Entry::new(KEYRING_SERVICE, key)?.get_password()?,
+            )),
+            SecretRef::Vault(_) if self.is_locked() => Ok(None),
+            SecretRef::Vault(key) => Ok(Some(self.vault.get(key).cloned().unwrap_or_default())),
+        }
+    }
+
+    pub fn delete(&mut self, secret: &SecretRef) -> Result<(), Box<dyn Error>> {
+        match secret {
+            SecretRef::None => {}
+            SecretRef::Keyring(key) => {
+                keyring::Entry::new(KEYRING_SERVICE, key)?.delete_credential()?;
+            }
+            SecretRef::Vault(key) => {
+                if self.vault.remove(key).is_some() {
+                    self.write_vault()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_vault(&self) -> Result<(), Box<dyn Error>> {
+        let (Some(path), Some(master_password)) = (&self.vault_path, &self.master_password) else {
+            return Err("secret vault is locked".into());
+        };
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(master_password, &salt)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let data = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, &*serde_json::to_vec(&self.vault)?)
+            .map_err(|_| "cannot encrypt vault")?;
+        let file = VaultFile {
+            salt: to_hex(&salt),
+            nonce: to_hex(&nonce),
+            data: to_hex(&data),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(&file)?)?;
+        Ok(())
+    }
+}
+
+fn derive_key(master_password: &str, salt: &[u8]) -> Result<[u8; 32], Box<dyn Error>> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(master_password.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("cannot derive key: {}", e))?;
+    Ok(key)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .ok_or_else(|| "odd hex length".into())
+                .and_then(|byte| u8::from_str_radix(byte, 16).map_err(|e| e.into()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(path: &std::path::Path) -> SecretStore {
+        SecretStore {
+            vault_path: Some(path.to_owned()),
+            master_password: None,
+            vault: HashMap::new(),
+            use_keyring: false,
+        }
+    }
+
+    fn vault_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("oldqtt_{}_{:x}.vault", name, fastrand::u64(..)))
+    }
+
+    #[test]
+    fn vault_round_trip() {
+        let path = vault_path("round_trip");
+        let mut writer = store(&path);
+        writer.unlock("master").unwrap();
+        let secret = writer.store("broker", "hunter2").unwrap();
+        assert_eq!(secret, SecretRef::Vault(String::from("broker")));
+
+        let mut reader = store(&path);
+        assert_eq!(reader.load(&secret).unwrap(), None);
+        reader.unlock("master").unwrap();
+        assert_eq!(reader.load(&secret).unwrap().as_deref(), Some("hunter2"));
+
+        let mut wrong = store(&path);
+        assert!(wrong.unlock("wrong").is_err());
+        assert!(wrong.is_locked());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn bad_nonce_is_an_error() {
+        let path = vault_path("bad_nonce");
+        let mut writer = store(&path);
+        writer.unlock("master").unwrap();
+        writer.store("broker", "hunter2").unwrap();
+        let mut file: VaultFile =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        file.nonce.truncate(10);
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(store(&path).unlock("master").is_err());
+        let _ = fs::remove_file(path);
+    }
+}
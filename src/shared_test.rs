@@ -0,0 +1,236 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use egui::Ui;
+use rumqttc::{Client, Event, Incoming, QoS, SubscribeReasonCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::MqttServer,
+    mqtt_servermanager::{mqtt_options, MqttServerManager},
+    topic,
+};
+
+/// Time given to the workers to subscribe before test messages go out.
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SharedTestConfig {
+    pub group: String,
+    pub filter: String,
+    pub clients: u32,
+    /// Messages the test publishes to `topic` itself, zero only counts what arrives.
+    pub messages: u32,
+    pub topic: String,
+}
+
+impl Default for SharedTestConfig {
+    fn default() -> Self {
+        SharedTestConfig {
+            group: String::from("oldqtt"),
+            filter: String::from("oldqtt/shared/#"),
+            clients: 3,
+            messages: 100,
+            topic: String::from("oldqtt/shared/test"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Worker {
+    received: AtomicU64,
+    subscribed: AtomicBool,
+    rejected: AtomicBool,
+}
+
+/// Local clients sharing one subscription, disconnected when dropped.
+pub struct SharedTest {
+    config: SharedTestConfig,
+    workers: Vec<Arc<Worker>>,
+    clients: Vec<Client>,
+    published: Arc<AtomicU32>,
+}
+
+impl SharedTest {
+    pub fn start(id: u32, server: &MqttServer, config: SharedTestConfig) -> Self {
+        let filter = topic::share(&config.group, &config.filter);
+        let mut workers = vec![];
+        let mut clients = vec![];
+        for i in 0..config.clients {
+            let options = mqtt_options(server, format!("oldqtt_{:x}_share_{}", id, i));
+            let (client, mut connection) = Client::new(options, 100);
+            if let Err(e) = client.subscribe(&filter, QoS::AtLeastOnce) {
+                log::error!("Shared subscription worker {} cannot subscribe: {}", i, e);
+            }
+            let worker = Arc::new(Worker::default());
+            let worker_c = worker.clone();
+            std::thread::spawn(move || {
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Incoming(Incoming::SubAck(ack))) => {
+                            let rejected = ack.return_codes.contains(&SubscribeReasonCode::Failure);
+                            worker_c.rejected.store(rejected, Ordering::Relaxed);
+                            worker_c.subscribed.store(true, Ordering::Relaxed);
+                        }
+                        Ok(Event::Incoming(Incoming::Publish(_))) => {
+                            worker_c.received.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) | Err(_) => return,
+                        _ => {}
+                    }
+                }
+            });
+            workers.push(worker);
+            clients.push(client);
+        }
+        let published = Arc::new(AtomicU32::new(0));
+        if config.messages > 0 {
+            let options = mqtt_options(server, format!("oldqtt_{:x}_share_pub", id));
+            let (client, mut connection) = Client::new(options, 100);
+            std::thread::spawn(move || {
+                for event in connection.iter() {
+                    if let Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) | Err(_) = event {
+                        return;
+                    }
+                }
+            });
+            let (workers_c, published_c, config_c) =
+                (workers.clone(), published.clone(), config.clone());
+            let publisher = client.clone();
+            std::thread::spawn(move || {
+                let deadline = Instant::now() + SUBSCRIBE_TIMEOUT;
+                while Instant::now() < deadline
+                    && !workers_c
+                        .iter()
+                        .all(|worker| worker.subscribed.load(Ordering::Relaxed))
+                {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                for seq in 0..config_c.messages {
+                    let payload = seq.to_string();
+                    match publisher.publish(&config_c.topic, QoS::AtLeastOnce, false, payload) {
+                        Ok(()) => published_c.fetch_add(1, Ordering::Relaxed),
+                        Err(_) => return,
+                    };
+                }
+            });
+            clients.push(client);
+        }
+        SharedTest {
+            config,
+            workers,
+            clients,
+            published,
+        }
+    }
+
+    pub fn published(&self) -> u32 {
+        self.published.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SharedTest {
+    fn drop(&mut self) {
+        for client in &self.clients {
+            let _ = client.disconnect();
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SharedTestPanel {
+    config: SharedTestConfig,
+}
+
+impl SharedTestPanel {
+    /// Shows config and distribution, returns the config when a test should start.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        id: u32,
+        manager: &mut MqttServerManager,
+    ) -> Option<SharedTestConfig> {
+        let mut start = None;
+        ui.collapsing("Shared subscription test", |ui| {
+            let running = manager.shared_test(id).is_some();
+            ui.add_enabled_ui(!running, |ui| {
+                let config = &mut self.config;
+                egui::Grid::new("shared_test_config").show(ui, |ui| {
+                    ui.label("group");
+                    ui.text_edit_singleline(&mut config.group);
+                    ui.label("filter");
+                    ui.text_edit_singleline(&mut config.filter);
+                    ui.end_row();
+                    ui.label("clients");
+                    ui.add(egui::DragValue::new(&mut config.clients).range(1..=100));
+                    ui.label("publish");
+                    ui.add(egui::DragValue::new(&mut config.messages).suffix(" messages"));
+                    ui.end_row();
+                    ui.label("to topic");
+                    ui.text_edit_singleline(&mut config.topic);
+                    ui.end_row();
+                });
+            });
+            let valid =
+                topic::validate_filter(&topic::share(&self.config.group, &self.config.filter));
+            ui.horizontal(|ui| {
+                if running {
+                    if ui.button("stop").clicked() {
+                        manager.stop_shared_test(id);
+                    }
+                } else if ui
+                    .add_enabled(valid.is_ok(), egui::Button::new("start"))
+                    .clicked()
+                {
+                    start = Some(self.config.clone());
+                }
+                if let Err(e) = valid {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+            });
+            let Some(test) = manager.shared_test(id) else {
+                return;
+            };
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
+            let counts: Vec<u64> = test
+                .workers
+                .iter()
+                .map(|worker| worker.received.load(Ordering::Relaxed))
+                .collect();
+            let total: u64 = counts.iter().sum();
+            ui.small(format!(
+                "{} published, {} received by '{}'",
+                test.published(),
+                total,
+                topic::share(&test.config.group, &test.config.filter)
+            ));
+            egui::Grid::new("shared_test_distribution")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (i, (worker, count)) in test.workers.iter().zip(&counts).enumerate() {
+                        ui.label(format!("client {}", i));
+                        let share = *count as f32 / total.max(1) as f32;
+                        ui.add(
+                            egui::ProgressBar::new(share)
+                                .desired_width(150.0)
+                                .text(format!("{} ({:.0}%)", count, share * 100.0)),
+                        );
+                        if worker.rejected.load(Ordering::Relaxed) {
+                            ui.colored_label(ui.visuals().error_fg_color, "subscription rejected");
+                        } else if !worker.subscribed.load(Ordering::Relaxed) {
+                            ui.small("subscribing");
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+        start
+    }
+}
@@ -0,0 +1,502 @@
+use std::sync::mpsc::Sender;
+
+use egui::{ahash::HashMap, Context, Ui};
+use rumqttc::{LastWill, Publish};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::MqttServer,
+    mqtt_servermanager::{
+        mqtt_options, MqttServerManager, MqttServerManagerEvent, Publisher, Server,
+    },
+    publish::{qos_combo, PublishTemplate},
+    scheduler::{RunningSchedule, Schedule, ScheduledPublish},
+    subscription::Subscription,
+    topic,
+};
+
+/// A topic a simulated device publishes to periodically.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SimTopic {
+    pub message: PublishTemplate,
+    pub interval_ms: u64,
+}
+
+/// Answers commands received on `filter` with `response`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SimHandler {
+    pub filter: String,
+    pub response: PublishTemplate,
+}
+
+/// A kind of device, instantiated as many clients of its own.
+///
+/// Topics, payloads and variables may use `{{n}}` for the instance number, `{{client_id}}`
+/// and `{{name}}` of the variables. Payloads, the last will's included, are rendered like
+/// any other publish afterwards, so `{{rand(a,b)}}` and friends generate values. Responses may use `{{topic}}` and
+/// `{{request}}` of the command they answer.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SimulatorDefinition {
+    pub id: u32,
+    pub name: String,
+    pub server: u32,
+    pub instances: u32,
+    pub client_id: String,
+    pub variables: Vec<(String, String)>,
+    pub topics: Vec<SimTopic>,
+    pub will_enabled: bool,
+    pub will: PublishTemplate,
+    pub handlers: Vec<SimHandler>,
+}
+
+impl Default for SimulatorDefinition {
+    fn default() -> Self {
+        SimulatorDefinition {
+            id: fastrand::u32(..),
+            name: String::from("new device"),
+            server: 0,
+            instances: 1,
+            client_id: String::from("oldqtt_sim_{{n}}"),
+            variables: vec![(String::from("device"), String::from("device-{{n}}"))],
+            topics: vec![SimTopic {
+                message: PublishTemplate {
+                    topic: String::from("devices/{{device}}/temperature"),
+                    payload: String::from("{{randf(18,25)}}"),
+                    ..PublishTemplate::default()
+                },
+                interval_ms: 1000,
+            }],
+            will_enabled: true,
+            will: PublishTemplate {
+                topic: String::from("devices/{{device}}/status"),
+                payload: String::from("offline"),
+                retain: true,
+                ..PublishTemplate::default()
+            },
+            handlers: vec![SimHandler {
+                filter: String::from("devices/{{device}}/cmd"),
+                response: PublishTemplate {
+                    topic: String::from("devices/{{device}}/cmd/response"),
+                    payload: String::from("{{request}}"),
+                    ..PublishTemplate::default()
+                },
+            }],
+        }
+    }
+}
+
+/// Replaces `{{name}}` of every variable, in order.
+fn expand(text: &str, variables: &[(String, String)]) -> String {
+    variables
+        .iter()
+        .fold(text.to_owned(), |text, (name, value)| {
+            text.replace(&format!("{{{{{}}}}}", name), value)
+        })
+}
+
+fn expand_message(message: &PublishTemplate, variables: &[(String, String)]) -> PublishTemplate {
+    PublishTemplate {
+        topic: expand(&message.topic, variables),
+        payload: expand(&message.payload, variables),
+        ..message.clone()
+    }
+}
+
+/// One simulated device with its own connection.
+struct SimInstance {
+    id: u32,
+    server: Server,
+    /// Stopped when dropped.
+    _schedules: Vec<RunningSchedule>,
+    handlers: Vec<SimHandler>,
+}
+
+pub struct RunningSimulator {
+    instances: Vec<SimInstance>,
+}
+
+impl RunningSimulator {
+    pub fn start(
+        definition: &SimulatorDefinition,
+        server: &MqttServer,
+        channel: Sender<MqttServerManagerEvent>,
+        ctx: &Context,
+    ) -> Self {
+        let mut instances = vec![];
+        for n in 1..=definition.instances {
+            let mut variables = vec![(String::from("n"), n.to_string())];
+            let client_id = expand(&definition.client_id, &variables);
+            variables.push((String::from("client_id"), client_id.clone()));
+            for (name, value) in &definition.variables {
+                variables.push((name.clone(), expand(value, &variables)));
+            }
+            let mut options = mqtt_options(server, client_id);
+            if definition.will_enabled {
+                let will = expand_message(&definition.will, &variables).render(0);
+                options.set_last_will(LastWill::new(
+                    will.topic.clone(),
+                    will.payload.clone(),
+                    will.qos(),
+                    will.retain,
+                ));
+            }
+            let id = fastrand::u32(..);
+            let mut server = Server::connect(options, channel.clone(), id, ctx.clone());
+            let handlers: Vec<SimHandler> = definition
+                .handlers
+                .iter()
+                .map(|handler| SimHandler {
+                    filter: expand(&handler.filter, &variables),
+                    response: expand_message(&handler.response, &variables),
+                })
+                .collect();
+            let filters: Vec<Subscription> = handlers
+                .iter()
+                .filter(|h| match topic::validate_filter(&h.filter) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::error!(
+                            "Simulator '{}' skips invalid filter '{}': {}",
+                            definition.name,
+                            h.filter,
+                            e
+                        );
+                        false
+                    }
+                })
+                .map(|h| Subscription::new(h.filter.clone()))
+                .collect();
+            if let Err(e) = server.sync_subs(&filters) {
+                log::error!("Simulator '{}' cannot subscribe: {}", definition.name, e);
+            }
+            let schedules = definition
+                .topics
+                .iter()
+                .filter_map(|topic| {
+                    let scheduled = ScheduledPublish {
+                        id: 0,
+                        message: expand_message(&topic.message, &variables),
+                        schedule: Schedule::Every {
+                            ms: topic.interval_ms.max(1),
+                        },
+                    };
+                    RunningSchedule::start(server.publisher().clone(), scheduled).ok()
+                })
+                .collect();
+            instances.push(SimInstance {
+                id,
+                server,
+                _schedules: schedules,
+                handlers,
+            });
+        }
+        RunningSimulator { instances }
+    }
+
+    pub fn instances(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Answers `event` if it was received by one of the instances.
+    pub fn handle(&self, event: &MqttServerManagerEvent) -> bool {
+        let Some(instance) = self.instances.iter().find(|i| i.id == event.client) else {
+            return false;
+        };
+        for handler in &instance.handlers {
+            if topic::matches(&handler.filter, &event.event.topic) {
+                respond(instance.server.publisher(), &handler.response, &event.event);
+            }
+        }
+        true
+    }
+}
+
+fn respond(publisher: &Publisher, response: &PublishTemplate, request: &Publish) {
+    let response = response_message(response, request, publisher.next_seq());
+    if let Err(e) = publisher.send_rendered(&response) {
+        log::error!("Simulated device cannot respond: {}", e);
+    }
+}
+
+/// Renders the placeholders of the response before the request is filled in, so
+/// placeholders arriving in a request are published as they are.
+fn response_message(response: &PublishTemplate, request: &Publish, seq: u64) -> PublishTemplate {
+    let rendered = response.render(seq);
+    let variables = [
+        (String::from("topic"), request.topic.clone()),
+        (
+            String::from("request"),
+            String::from_utf8_lossy(&request.payload).into_owned(),
+        ),
+    ];
+    expand_message(&rendered, &variables)
+}
+
+impl Drop for RunningSimulator {
+    fn drop(&mut self) {
+        for instance in &self.instances {
+            if let Err(e) = instance.server.client().disconnect() {
+                log::error!("Cannot disconnect simulated device: {}", e);
+            }
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulatorsWindow {
+    #[serde(skip)]
+    pub open: bool,
+    simulators: Vec<SimulatorDefinition>,
+    selected: Option<u32>,
+}
+
+impl SimulatorsWindow {
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        manager: &mut MqttServerManager,
+        servers: &HashMap<u32, MqttServer>,
+    ) {
+        let server_name = |id: u32| {
+            servers
+                .get(&id)
+                .map(MqttServer::name)
+                .unwrap_or_else(|| String::from("-"))
+        };
+        egui::Window::new("Simulators")
+            .open(&mut self.open)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for simulator in &self.simulators {
+                        let label = match manager.simulator(simulator.id) {
+                            Some(running) => {
+                                format!("{} ({} running)", simulator.name, running.instances())
+                            }
+                            None => simulator.name.clone(),
+                        };
+                        if ui
+                            .selectable_label(self.selected == Some(simulator.id), label)
+                            .clicked()
+                        {
+                            self.selected = Some(simulator.id);
+                        }
+                    }
+                    if ui.button("New").clicked() {
+                        let simulator = SimulatorDefinition::default();
+                        self.selected = Some(simulator.id);
+                        self.simulators.push(simulator);
+                    }
+                });
+                ui.separator();
+                let Some(simulator) = self
+                    .simulators
+                    .iter_mut()
+                    .find(|simulator| Some(simulator.id) == self.selected)
+                else {
+                    return;
+                };
+                let running = manager.simulator(simulator.id).is_some();
+                let mut delete = false;
+                ui.horizontal(|ui| {
+                    match (running, servers.get(&simulator.server)) {
+                        (true, _) => {
+                            if ui.button("Stop").clicked() {
+                                manager.stop_simulator(simulator.id);
+                            }
+                        }
+                        (false, Some(server)) => {
+                            if ui.button("Start").clicked() {
+                                manager.start_simulator(simulator, server, ctx);
+                            }
+                        }
+                        (false, None) => {
+                            ui.add_enabled(false, egui::Button::new("Start"))
+                                .on_disabled_hover_text("choose a server first");
+                        }
+                    }
+                    if ui.button("Delete").clicked() {
+                        delete = true;
+                    }
+                });
+                ui.add_enabled_ui(!running, |ui| {
+                    definition_ui(ui, simulator, servers, &server_name);
+                });
+                if delete {
+                    manager.stop_simulator(simulator.id);
+                    let id = simulator.id;
+                    self.simulators.retain(|simulator| simulator.id != id);
+                    self.selected = None;
+                }
+            });
+    }
+}
+
+fn definition_ui(
+    ui: &mut Ui,
+    simulator: &mut SimulatorDefinition,
+    servers: &HashMap<u32, MqttServer>,
+    server_name: &dyn Fn(u32) -> String,
+) {
+    egui::Grid::new("simulator").show(ui, |ui| {
+        ui.label("name");
+        ui.text_edit_singleline(&mut simulator.name);
+        ui.end_row();
+        ui.label("server");
+        egui::ComboBox::from_id_salt("simulator_server")
+            .selected_text(server_name(simulator.server))
+            .show_ui(ui, |ui| {
+                for (id, server) in servers.iter() {
+                    ui.selectable_value(&mut simulator.server, *id, server.name());
+                }
+            });
+        ui.end_row();
+        ui.label("instances");
+        ui.add(egui::DragValue::new(&mut simulator.instances).range(1..=1000));
+        ui.end_row();
+        ui.label("client id");
+        ui.text_edit_singleline(&mut simulator.client_id);
+        ui.end_row();
+    });
+    ui.collapsing("Variables", |ui| {
+        let mut delete = None;
+        for (index, (name, value)) in simulator.variables.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(name).desired_width(100.0));
+                ui.text_edit_singleline(value);
+                if ui.button("Del").clicked() {
+                    delete = Some(index);
+                }
+            });
+        }
+        if let Some(index) = delete {
+            simulator.variables.remove(index);
+        }
+        if ui.button("Add").clicked() {
+            simulator.variables.push(Default::default());
+        }
+    });
+    ui.collapsing("Topics", |ui| {
+        let mut delete = None;
+        for (index, topic) in simulator.topics.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                message_ui(ui, &mut topic.message);
+                ui.horizontal(|ui| {
+                    ui.label("every");
+                    ui.add(egui::DragValue::new(&mut topic.interval_ms).suffix(" ms"));
+                    if ui.button("Del").clicked() {
+                        delete = Some(index);
+                    }
+                });
+            });
+            ui.separator();
+        }
+        if let Some(index) = delete {
+            simulator.topics.remove(index);
+        }
+        if ui.button("Add").clicked() {
+            simulator.topics.push(SimTopic {
+                interval_ms: 1000,
+                ..SimTopic::default()
+            });
+        }
+    });
+    ui.collapsing("Last will", |ui| {
+        ui.checkbox(&mut simulator.will_enabled, "enabled");
+        ui.add_enabled_ui(simulator.will_enabled, |ui| {
+            message_ui(ui, &mut simulator.will);
+        });
+    });
+    ui.collapsing("Command handlers", |ui| {
+        let mut delete = None;
+        for (index, handler) in simulator.handlers.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("on");
+                    ui.text_edit_singleline(&mut handler.filter);
+                    if ui.button("Del").clicked() {
+                        delete = Some(index);
+                    }
+                });
+                message_ui(ui, &mut handler.response);
+            });
+            ui.separator();
+        }
+        if let Some(index) = delete {
+            simulator.handlers.remove(index);
+        }
+        if ui.button("Add").clicked() {
+            simulator.handlers.push(SimHandler::default());
+        }
+    });
+}
+
+fn message_ui(ui: &mut Ui, message: &mut PublishTemplate) {
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut message.topic).hint_text("topic"));
+        qos_combo(ui, "simulator_qos", &mut message.qos);
+        ui.checkbox(&mut message.retain, "retain");
+    });
+    ui.add(
+        egui::TextEdit::multiline(&mut message.payload)
+            .hint_text("payload")
+            .desired_rows(2),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use rumqttc::QoS;
+
+    use super::*;
+
+    #[test]
+    fn placeholders_in_requests_are_not_rendered() {
+        std::env::set_var("OLDQTT_SIMULATOR_SECRET", "secret");
+        let response = PublishTemplate {
+            topic: String::from("{{topic}}/reply/{{seq}}"),
+            payload: String::from("{{request}}"),
+            ..PublishTemplate::default()
+        };
+        let request = Publish::new(
+            "cmd/{{env(OLDQTT_SIMULATOR_SECRET)}}",
+            QoS::AtMostOnce,
+            "{{env(OLDQTT_SIMULATOR_SECRET)}}",
+        );
+        let message = response_message(&response, &request, 7);
+        assert_eq!(
+            message.topic,
+            "cmd/{{env(OLDQTT_SIMULATOR_SECRET)}}/reply/7"
+        );
+        assert_eq!(message.payload, "{{env(OLDQTT_SIMULATOR_SECRET)}}");
+    }
+
+    #[test]
+    fn responses_advance_seq() {
+        let (requests, sent) = flume::unbounded();
+        let publisher = Publisher::new(1, rumqttc::Client::from_sender(requests));
+        let response = PublishTemplate {
+            topic: String::from("{{topic}}/reply"),
+            payload: String::from("{{seq}}"),
+            ..PublishTemplate::default()
+        };
+        let request = Publish::new("cmd", QoS::AtMostOnce, "");
+        respond(&publisher, &response, &request);
+        respond(&publisher, &response, &request);
+        let payloads: Vec<String> = sent
+            .try_iter()
+            .filter_map(|request| match request {
+                rumqttc::Request::Publish(publish) => {
+                    assert_eq!(publish.topic, "cmd/reply");
+                    Some(String::from_utf8_lossy(&publish.payload).into_owned())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(payloads, ["0", "1"]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use egui::Color32;
+use rumqttc::{Event, Incoming, Outgoing, QoS, SubscribeReasonCode};
+use serde::{Deserialize, Serialize};
+
+/// A subscription filter of a server with its display settings.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "StoredSubscription")]
+pub struct Subscription {
+    pub filter: String,
+    /// Disabled subscriptions are kept but unsubscribed.
+    pub enabled: bool,
+    pub alias: String,
+    /// Tint of the messages in the table.
+    pub color: Option<Color32>,
+    #[serde(skip)]
+    pub count: u64,
+}
+
+impl Subscription {
+    pub fn new(filter: String) -> Self {
+        Subscription {
+            filter,
+            enabled: true,
+            alias: String::new(),
+            color: None,
+            count: 0,
+        }
+    }
+
+    /// Alias if one is set, the filter otherwise.
+    pub fn label(&self) -> &str {
+        match self.alias.is_empty() {
+            true => &self.filter,
+            false => &self.alias,
+        }
+    }
+}
+
+/// Subscriptions used to be stored as plain filters.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSubscription {
+    Filter(String),
+    Full {
+        filter: String,
+        #[serde(default = "enabled")]
+        enabled: bool,
+        #[serde(default)]
+        alias: String,
+        #[serde(default)]
+        color: Option<Color32>,
+    },
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl From<StoredSubscription> for Subscription {
+    fn from(stored: StoredSubscription) -> Self {
+        match stored {
+            StoredSubscription::Filter(filter) => Subscription::new(filter),
+            StoredSubscription::Full {
+                filter,
+                enabled,
+                alias,
+                color,
+            } => Subscription {
+                enabled,
+                alias,
+                color,
+                ..Subscription::new(filter)
+            },
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum SubStatus {
+    Pending,
+    Granted(QoS),
+    /// The broker answered with the SUBACK failure code 0x80.
+    Rejected,
+    Unsubscribed,
+    /// The request never made it to the broker.
+    Failed(String),
+}
+
+#[derive(Default)]
+struct Requests {
+    /// Filters requested but not yet sent, in the order the event loop sends them.
+    queued_subs: VecDeque<String>,
+    queued_unsubs: VecDeque<String>,
+    sent_subs: HashMap<u16, String>,
+    sent_unsubs: HashMap<u16, String>,
+    status: HashMap<String, SubStatus>,
+}
+
+/// Status of the subscriptions of one connection, updated from its event loop.
+///
+/// The client doesn't hand out packet ids, so requests are matched with their
+/// acknowledgements by the order they are sent in.
+#[derive(Default)]
+pub struct SubTracker {
+    requests: Mutex<Requests>,
+}
+
+impl SubTracker {
+    /// Call right before handing a subscribe request to the client.
+    pub fn subscribing(&self, filter: &str) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.queued_subs.push_back(filter.to_owned());
+            requests
+                .status
+                .insert(filter.to_owned(), SubStatus::Pending);
+        }
+    }
+
+    /// Call right before handing an unsubscribe request to the client.
+    pub fn unsubscribing(&self, filter: &str) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.queued_unsubs.push_back(filter.to_owned());
+        }
+    }
+
+    /// Takes back the last request after the client refused it.
+    pub fn failed(&self, filter: &str, subscribe: bool, error: String) {
+        if let Ok(mut requests) = self.requests.lock() {
+            match subscribe {
+                true => requests.queued_subs.pop_back(),
+                false => requests.queued_unsubs.pop_back(),
+            };
+            requests
+                .status
+                .insert(filter.to_owned(), SubStatus::Failed(error));
+        }
+    }
+
+    pub fn set(&self, filter: &str, status: SubStatus) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.status.insert(filter.to_owned(), status);
+        }
+    }
+
+    /// Returns whether the status of a subscription changed.
+    pub fn record(&self, event: &Event) -> bool {
+        let Ok(mut requests) = self.requests.lock() else {
+            return false;
+        };
+        match event {
+            Event::Outgoing(Outgoing::Subscribe(pkid)) => {
+                if let Some(filter) = requests.queued_subs.pop_front() {
+                    requests.sent_subs.insert(*pkid, filter);
+                }
+            }
+            Event::Outgoing(Outgoing::Unsubscribe(pkid)) => {
+                if let Some(filter) = requests.queued_unsubs.pop_front() {
+                    requests.sent_unsubs.insert(*pkid, filter);
+                }
+            }
+            Event::Incoming(Incoming::SubAck(ack)) => {
+                if let Some(filter) = requests.sent_subs.remove(&ack.pkid) {
+                    let status = match ack.return_codes.first() {
+                        Some(SubscribeReasonCode::Success(qos)) => SubStatus::Granted(*qos),
+                        _ => SubStatus::Rejected,
+                    };
+                    requests.status.insert(filter, status);
+                    return true;
+                }
+            }
+            Event::Incoming(Incoming::UnsubAck(ack)) => {
+                if let Some(filter) = requests.sent_unsubs.remove(&ack.pkid) {
+                    requests.status.insert(filter, SubStatus::Unsubscribed);
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    pub fn status(&self, filter: &str) -> Option<SubStatus> {
+        self.requests
+            .lock()
+            .ok()
+            .and_then(|requests| requests.status.get(filter).cloned())
+    }
+}
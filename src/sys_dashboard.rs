@@ -0,0 +1,160 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+    time::Instant,
+};
+
+use egui::Context;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+
+pub const SYS_FILTER: &str = "$SYS/#";
+const MAX_POINTS: usize = 720;
+
+/// Well known `$SYS` keys with numeric values, as published by mosquitto and others.
+const METRICS: [(&str, &str); 10] = [
+    ("$SYS/broker/clients/connected", "clients connected"),
+    ("$SYS/broker/subscriptions/count", "subscriptions"),
+    ("$SYS/broker/retained messages/count", "retained messages"),
+    (
+        "$SYS/broker/load/messages/received/1min",
+        "msg/min received",
+    ),
+    ("$SYS/broker/load/messages/sent/1min", "msg/min sent"),
+    ("$SYS/broker/load/bytes/received/1min", "bytes/min received"),
+    ("$SYS/broker/load/bytes/sent/1min", "bytes/min sent"),
+    ("$SYS/broker/messages/received", "messages received"),
+    ("$SYS/broker/messages/sent", "messages sent"),
+    ("$SYS/broker/heap/current", "heap bytes"),
+];
+
+/// Charts grouping metrics of similar magnitude.
+const CHARTS: [(&str, &[&str]); 3] = [
+    (
+        "clients",
+        &[
+            "$SYS/broker/clients/connected",
+            "$SYS/broker/subscriptions/count",
+        ],
+    ),
+    (
+        "messages",
+        &[
+            "$SYS/broker/load/messages/received/1min",
+            "$SYS/broker/load/messages/sent/1min",
+        ],
+    ),
+    (
+        "bytes",
+        &[
+            "$SYS/broker/load/bytes/received/1min",
+            "$SYS/broker/load/bytes/sent/1min",
+        ],
+    ),
+];
+
+/// Latest `$SYS` values of a server and the history of the known metrics.
+pub struct SysState {
+    started: Instant,
+    values: Mutex<BTreeMap<String, String>>,
+    history: Mutex<HashMap<String, VecDeque<[f64; 2]>>>,
+}
+
+impl SysState {
+    pub fn new() -> Self {
+        SysState {
+            started: Instant::now(),
+            values: Mutex::new(BTreeMap::new()),
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, topic: &str, payload: &[u8]) {
+        let value = String::from_utf8_lossy(payload).into_owned();
+        if METRICS.iter().any(|(key, _)| *key == topic) {
+            // values like "123 seconds" carry a unit after the number
+            let number = value.split_whitespace().next().and_then(|n| n.parse().ok());
+            if let (Some(number), Ok(mut history)) = (number, self.history.lock()) {
+                let points = history.entry(topic.to_owned()).or_default();
+                points.push_back([self.started.elapsed().as_secs_f64(), number]);
+                if points.len() > MAX_POINTS {
+                    points.pop_front();
+                }
+            }
+        }
+        if let Ok(mut values) = self.values.lock() {
+            values.insert(topic.to_owned(), value);
+        }
+    }
+
+    fn value(&self, topic: &str) -> Option<String> {
+        self.values.lock().ok()?.get(topic).cloned()
+    }
+}
+
+pub fn window(ctx: &Context, id: u32, name: &str, open: &mut bool, state: Option<&SysState>) {
+    egui::Window::new(format!("$SYS: {}", name))
+        .id(format!("sys_{}", id).into())
+        .open(open)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            let Some(state) = state else {
+                ui.label("not connected");
+                return;
+            };
+            let value = |topic: &str| state.value(topic).unwrap_or_else(|| String::from("-"));
+            ui.small(format!(
+                "{} - uptime {}",
+                value("$SYS/broker/version"),
+                value("$SYS/broker/uptime")
+            ));
+            ui.separator();
+            egui::Grid::new(format!("sys_metrics_{}", id))
+                .striped(true)
+                .show(ui, |ui| {
+                    for (topic, label) in METRICS {
+                        ui.label(label);
+                        ui.label(value(topic));
+                        ui.end_row();
+                    }
+                });
+            let history = state
+                .history
+                .lock()
+                .map(|history| history.clone())
+                .unwrap_or_default();
+            for (chart, topics) in CHARTS {
+                Plot::new(format!("sys_{}_{}", chart, id))
+                    .height(100.0)
+                    .legend(Legend::default())
+                    .x_axis_label("s")
+                    .show(ui, |plot| {
+                        for topic in topics {
+                            let label = METRICS
+                                .iter()
+                                .find(|(key, _)| key == topic)
+                                .map(|(_, label)| *label)
+                                .unwrap_or(topic);
+                            let points: PlotPoints = history
+                                .get(*topic)
+                                .map(|points| points.iter().copied().collect())
+                                .unwrap_or_default();
+                            plot.line(Line::new(points).name(label));
+                        }
+                    });
+            }
+            ui.collapsing("all $SYS topics", |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        if let Ok(values) = state.values.lock() {
+                            for (topic, value) in values.iter() {
+                                ui.horizontal(|ui| {
+                                    ui.small(topic);
+                                    ui.label(value);
+                                });
+                            }
+                        }
+                    });
+            });
+        });
+}
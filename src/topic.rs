@@ -0,0 +1,154 @@
+const SHARE_PREFIX: &str = "$share/";
+/// Topic names and filters are length prefixed with two bytes on the wire.
+const MAX_LEN: usize = u16::MAX as usize;
+
+/// Whether `topic` matches the subscription `filter`, honouring `+` and `#` wildcards.
+pub fn matches(filter: &str, topic: &str) -> bool {
+    let filter = shared(filter).map_or(filter, |(_, filter)| filter);
+    // wildcards at the first level don't match topics like `$SYS/...`
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false;
+    }
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Group and filter of a `$share/group/filter` subscription.
+pub fn shared(filter: &str) -> Option<(&str, &str)> {
+    filter.strip_prefix(SHARE_PREFIX)?.split_once('/')
+}
+
+pub fn share(group: &str, filter: &str) -> String {
+    format!("{}{}/{}", SHARE_PREFIX, group, filter)
+}
+
+/// Checks the syntax of a subscription filter, including shared subscriptions.
+pub fn validate_filter(filter: &str) -> Result<(), String> {
+    if filter.starts_with(SHARE_PREFIX) {
+        let Some((group, filter)) = shared(filter) else {
+            return Err(String::from(
+                "shared subscription needs a filter after the group",
+            ));
+        };
+        if group.is_empty() {
+            return Err(String::from("share group must not be empty"));
+        }
+        if group.contains(['+', '#']) {
+            return Err(String::from("share group must not contain wildcards"));
+        }
+        return validate_filter(filter);
+    }
+    if filter.is_empty() {
+        return Err(String::from("filter must not be empty"));
+    }
+    check_common(filter)?;
+    let levels: Vec<&str> = filter.split('/').collect();
+    for (i, level) in levels.iter().enumerate() {
+        if level.contains('#') && (*level != "#" || i != levels.len() - 1) {
+            return Err(String::from(
+                "'#' must be a whole level at the end of the filter",
+            ));
+        }
+        if level.contains('+') && *level != "+" {
+            return Err(String::from("'+' must be a whole level"));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the syntax of a topic name to publish to.
+pub fn validate_topic(topic: &str) -> Result<(), String> {
+    if topic.is_empty() {
+        return Err(String::from("topic must not be empty"));
+    }
+    if topic.contains(['+', '#']) {
+        return Err(String::from("topic must not contain wildcards"));
+    }
+    check_common(topic)
+}
+
+fn check_common(name: &str) -> Result<(), String> {
+    if name.len() > MAX_LEN {
+        return Err(format!("longer than {} bytes", MAX_LEN));
+    }
+    if name.contains('\0') {
+        return Err(String::from("must not contain null characters"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_levels() {
+        assert!(matches("a/+/c", "a/b/c"));
+        assert!(matches("a/+", "a/"));
+        assert!(!matches("a/+", "a/b/c"));
+        assert!(!matches("a/+/c", "a/c"));
+        assert!(matches("a/#", "a/b/c"));
+        assert!(matches("#", "a/b"));
+        assert!(matches("+/+", "/a"));
+        assert!(!matches("a/b", "a/b/c"));
+    }
+
+    #[test]
+    fn multi_level_wildcard_matches_parent() {
+        assert!(matches("a/#", "a"));
+        assert!(!matches("a/#", "ab"));
+        assert!(!matches("a/+", "a"));
+    }
+
+    #[test]
+    fn leading_wildcards_skip_dollar_topics() {
+        assert!(!matches("#", "$SYS/broker/uptime"));
+        assert!(!matches("+/broker/uptime", "$SYS/broker/uptime"));
+        assert!(matches("$SYS/#", "$SYS/broker/uptime"));
+        assert!(matches("$SYS/+/uptime", "$SYS/broker/uptime"));
+        assert!(!matches("$share/group/#", "$SYS/broker/uptime"));
+    }
+
+    #[test]
+    fn shared_subscriptions() {
+        assert_eq!(shared("$share/group/a/+"), Some(("group", "a/+")));
+        assert_eq!(shared("$share/group"), None);
+        assert_eq!(shared("a/b"), None);
+        assert_eq!(share("group", "a/#"), "$share/group/a/#");
+        assert!(matches("$share/group/a/+", "a/b"));
+        assert!(validate_filter("$share/group/a/#").is_ok());
+        assert!(validate_filter("$share/group").is_err());
+        assert!(validate_filter("$share//a").is_err());
+        assert!(validate_filter("$share/g+/a").is_err());
+        assert!(validate_filter("$share/group/a#").is_err());
+    }
+
+    #[test]
+    fn wildcard_placement() {
+        for valid in ["#", "+", "a/#", "+/b/#", "a/+/c", "/+", "a//b"] {
+            assert!(validate_filter(valid).is_ok(), "{}", valid);
+        }
+        for invalid in ["", "a/#/c", "#/a", "a#", "a/b#", "a+", "a/+b/c", "a\0b"] {
+            assert!(validate_filter(invalid).is_err(), "{}", invalid);
+        }
+        assert!(validate_filter(&"a".repeat(MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn topics_reject_wildcards() {
+        assert!(validate_topic("a/b").is_ok());
+        assert!(validate_topic("$SYS/x").is_ok());
+        for invalid in ["", "a/+", "a/#", "a+b", "a\0"] {
+            assert!(validate_topic(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use egui::Context;
+use egui_extras::{Column, TableBuilder};
+
+/// Window the message and byte rates are averaged over.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+const TOP_TALKERS: usize = 10;
+
+#[derive(Clone)]
+pub struct TopicStats {
+    pub count: u64,
+    pub bytes: u64,
+    pub max_size: usize,
+    pub last_seen: Instant,
+    recent: VecDeque<(Instant, usize)>,
+}
+
+impl TopicStats {
+    fn new() -> Self {
+        TopicStats {
+            count: 0,
+            bytes: 0,
+            max_size: 0,
+            last_seen: Instant::now(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, now: Instant, size: usize) {
+        self.count += 1;
+        self.bytes += size as u64;
+        self.max_size = self.max_size.max(size);
+        self.last_seen = now;
+        self.recent.push_back((now, size));
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((at, _)) = self.recent.front() {
+            if now.duration_since(*at) <= RATE_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn messages_per_sec(&self) -> f64 {
+        self.recent.len() as f64 / RATE_WINDOW.as_secs_f64()
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.recent
+            .iter()
+            .map(|(_, size)| *size as f64)
+            .sum::<f64>()
+            / RATE_WINDOW.as_secs_f64()
+    }
+
+    pub fn average_size(&self) -> f64 {
+        self.bytes as f64 / self.count.max(1) as f64
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum SortBy {
+    Topic,
+    #[default]
+    MessagesPerSec,
+    BytesPerSec,
+    Count,
+    AverageSize,
+    MaxSize,
+    LastSeen,
+}
+
+/// Traffic of a single server, overall and per topic.
+#[derive(Clone, Default)]
+pub struct TrafficStats {
+    total: Option<TopicStats>,
+    topics: HashMap<String, TopicStats>,
+    sort_by: SortBy,
+    ascending: bool,
+    top_talkers: bool,
+}
+
+impl TrafficStats {
+    pub fn record(&mut self, topic: &str, size: usize) {
+        let now = Instant::now();
+        self.total
+            .get_or_insert_with(TopicStats::new)
+            .record(now, size);
+        match self.topics.get_mut(topic) {
+            Some(stats) => stats.record(now, size),
+            None => {
+                let mut stats = TopicStats::new();
+                stats.record(now, size);
+                self.topics.insert(topic.to_owned(), stats);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.total = None;
+        self.topics.clear();
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        if let Some(total) = self.total.as_mut() {
+            total.expire(now);
+        }
+        for stats in self.topics.values_mut() {
+            stats.expire(now);
+        }
+    }
+
+    fn sorted(&self) -> Vec<(&String, &TopicStats)> {
+        let mut rows: Vec<(&String, &TopicStats)> = self.topics.iter().collect();
+        let (sort_by, top_talkers) = match self.top_talkers {
+            true => (SortBy::MessagesPerSec, true),
+            false => (self.sort_by, false),
+        };
+        rows.sort_by(|(a_topic, a), (b_topic, b)| match sort_by {
+            SortBy::Topic => a_topic.cmp(b_topic),
+            SortBy::MessagesPerSec => a.messages_per_sec().total_cmp(&b.messages_per_sec()),
+            SortBy::BytesPerSec => a.bytes_per_sec().total_cmp(&b.bytes_per_sec()),
+            SortBy::Count => a.count.cmp(&b.count),
+            SortBy::AverageSize => a.average_size().total_cmp(&b.average_size()),
+            SortBy::MaxSize => a.max_size.cmp(&b.max_size),
+            SortBy::LastSeen => a.last_seen.cmp(&b.last_seen),
+        });
+        if top_talkers {
+            rows.reverse();
+            rows.truncate(TOP_TALKERS);
+        } else if !self.ascending {
+            rows.reverse();
+        }
+        rows
+    }
+
+    pub fn window(&mut self, ctx: &Context, id: u32, name: &str, open: &mut bool) {
+        egui::Window::new(format!("Traffic: {}", name))
+            .id(format!("traffic_{}", id).into())
+            .open(open)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                ctx.request_repaint_after(Duration::from_secs(1));
+                self.expire();
+                match &self.total {
+                    Some(total) => ui.small(format!(
+                        "{} topics, {:.1} msg/s, {:.0} B/s, {} messages, {} bytes total",
+                        self.topics.len(),
+                        total.messages_per_sec(),
+                        total.bytes_per_sec(),
+                        total.count,
+                        total.bytes
+                    )),
+                    None => ui.small("no messages yet"),
+                };
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.top_talkers, "top talkers only");
+                    if ui.button("reset").clicked() {
+                        self.clear();
+                    }
+                });
+                ui.separator();
+                let columns = [
+                    ("Topic", SortBy::Topic),
+                    ("msg/s", SortBy::MessagesPerSec),
+                    ("B/s", SortBy::BytesPerSec),
+                    ("Count", SortBy::Count),
+                    ("Avg", SortBy::AverageSize),
+                    ("Max", SortBy::MaxSize),
+                    ("Last", SortBy::LastSeen),
+                ];
+                let mut clicked = None;
+                let now = Instant::now();
+                let rows = self.sorted();
+                TableBuilder::new(ui)
+                    .column(Column::remainder().at_least(120.0))
+                    .columns(Column::auto().at_least(50.0), columns.len() - 1)
+                    .striped(true)
+                    .resizable(true)
+                    .header(20.0, |mut header| {
+                        for (title, sort_by) in columns {
+                            header.col(|ui| {
+                                let marker = match (self.sort_by == sort_by, self.ascending) {
+                                    (true, true) => " ^",
+                                    (true, false) => " v",
+                                    _ => "",
+                                };
+                                if ui.button(format!("{}{}", title, marker)).clicked() {
+                                    clicked = Some(sort_by);
+                                }
+                            });
+                        }
+                    })
+                    .body(|body| {
+                        body.rows(16.0, rows.len(), |mut row| {
+                            let (topic, stats) = rows[row.index()];
+                            row.col(|ui| {
+                                ui.label(topic);
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.1}", stats.messages_per_sec()));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.0}", stats.bytes_per_sec()));
+                            });
+                            row.col(|ui| {
+                                ui.label(stats.count.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.0}", stats.average_size()));
+                            });
+                            row.col(|ui| {
+                                ui.label(stats.max_size.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(format!(
+                                    "{}s ago",
+                                    now.duration_since(stats.last_seen).as_secs()
+                                ));
+                            });
+                        });
+                    });
+                if let Some(sort_by) = clicked {
+                    self.ascending = self.sort_by == sort_by && !self.ascending;
+                    self.sort_by = sort_by;
+                    self.top_talkers = false;
+                }
+            });
+    }
+}